@@ -1,6 +1,9 @@
+use crate::models::screen::DEFAULT_SCREEN_SCROLLBACK_LINES;
+use crate::models::scrollback::DEFAULT_SCROLLBACK_BYTES;
+use crate::outgoing::DEFAULT_CLIENT_QUEUE_BYTES;
 use serde::{Deserialize, Serialize};
 use std::env;
-use std::path::PathBuf;
+use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -8,6 +11,14 @@ pub struct Config {
     pub port: u16,
     pub claude_path: String,
     pub max_sessions: usize,
+    pub scrollback_bytes: usize,
+    pub screen_scrollback_lines: usize,
+    pub shutdown_grace_ms: u64,
+    pub idle_after_secs: u64,
+    pub heartbeat_interval_secs: u64,
+    pub heartbeat_timeout_secs: u64,
+    pub client_queue_bytes: usize,
+    pub output_frame_interval_ms: u64,
     pub allowed_directories: Vec<String>,
 }
 
@@ -24,13 +35,45 @@ impl Config {
                 .unwrap_or_else(|_| "10".to_string())
                 .parse()
                 .unwrap_or(10),
+            scrollback_bytes: env::var("THRALLUI_SCROLLBACK_BYTES")
+                .unwrap_or_else(|_| DEFAULT_SCROLLBACK_BYTES.to_string())
+                .parse()
+                .unwrap_or(DEFAULT_SCROLLBACK_BYTES),
+            screen_scrollback_lines: env::var("THRALLUI_SCREEN_SCROLLBACK_LINES")
+                .unwrap_or_else(|_| DEFAULT_SCREEN_SCROLLBACK_LINES.to_string())
+                .parse()
+                .unwrap_or(DEFAULT_SCREEN_SCROLLBACK_LINES),
+            shutdown_grace_ms: env::var("THRALLUI_SHUTDOWN_GRACE_MS")
+                .unwrap_or_else(|_| "3000".to_string())
+                .parse()
+                .unwrap_or(3000),
+            idle_after_secs: env::var("THRALLUI_IDLE_AFTER_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .unwrap_or(300),
+            heartbeat_interval_secs: env::var("THRALLUI_HEARTBEAT_INTERVAL_SECS")
+                .unwrap_or_else(|_| "15".to_string())
+                .parse()
+                .unwrap_or(15),
+            heartbeat_timeout_secs: env::var("THRALLUI_HEARTBEAT_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "45".to_string())
+                .parse()
+                .unwrap_or(45),
+            client_queue_bytes: env::var("THRALLUI_CLIENT_QUEUE_BYTES")
+                .unwrap_or_else(|_| DEFAULT_CLIENT_QUEUE_BYTES.to_string())
+                .parse()
+                .unwrap_or(DEFAULT_CLIENT_QUEUE_BYTES),
+            output_frame_interval_ms: env::var("THRALLUI_OUTPUT_FRAME_INTERVAL_MS")
+                .unwrap_or_else(|_| "16".to_string())
+                .parse()
+                .unwrap_or(16),
             allowed_directories: vec![
                 env::var("THRALLUI_ALLOWED_DIRS").unwrap_or_else(|_| "/tmp".to_string())
             ],
         }
     }
 
-    pub fn is_directory_allowed(&self, path: &PathBuf) -> bool {
+    pub fn is_directory_allowed(&self, path: &Path) -> bool {
         let path_str = path.to_string_lossy();
         self.allowed_directories
             .iter()
@@ -45,6 +88,14 @@ impl Default for Config {
             port: 3000,
             claude_path: "claude".to_string(),
             max_sessions: 10,
+            scrollback_bytes: DEFAULT_SCROLLBACK_BYTES,
+            screen_scrollback_lines: DEFAULT_SCREEN_SCROLLBACK_LINES,
+            shutdown_grace_ms: 3000,
+            idle_after_secs: 300,
+            heartbeat_interval_secs: 15,
+            heartbeat_timeout_secs: 45,
+            client_queue_bytes: DEFAULT_CLIENT_QUEUE_BYTES,
+            output_frame_interval_ms: 16,
             allowed_directories: vec!["/home".to_string(), "/tmp".to_string()],
         }
     }
@@ -4,7 +4,12 @@
 #[cfg(feature = "server")]
 pub mod config;
 #[cfg(feature = "server")]
+mod outgoing;
+#[cfg(feature = "server")]
 pub mod process_manager;
+// Non-blocking PTY handles live on the server-side session model
+#[cfg(not(target_family = "wasm"))]
+pub mod pty_io;
 #[cfg(feature = "server")]
 pub mod session_handler;
 #[cfg(feature = "server")]
@@ -20,7 +25,7 @@ pub mod models;
 // Re-export commonly used items
 pub use models::{
     message::{ClientEvent, ServerEvent},
-    session::{SessionConfig, SessionInfo},
+    session::{SessionConfig, SessionInfo, SessionShutdown, SessionSignal, TerminalSize},
 };
 
 #[cfg(feature = "server")]
@@ -53,11 +58,9 @@ pub async fn get_allowed_directories() -> Result<Vec<String>, ServerFnError> {
     match fs::read_dir(base_dir) {
         Ok(entries) => {
             let mut children = Vec::new();
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    if let Some(path_str) = entry.path().to_str() {
-                        children.push(path_str.to_string());
-                    }
+            for entry in entries.flatten() {
+                if let Some(path_str) = entry.path().to_str() {
+                    children.push(path_str.to_string());
                 }
             }
             Ok(children)
@@ -75,29 +78,31 @@ pub async fn get_sessions() -> Result<Vec<crate::models::message::SessionInfo>,
     let app_state = crate::AppState::global();
 
     match app_state.session_manager.list_sessions().await {
-        Ok(sessions) => {
-            // Convert from session::SessionInfo to message::SessionInfo
-            Ok(sessions
-                .into_iter()
-                .map(|s| crate::models::message::SessionInfo {
-                    id: s.id,
-                    name: s.name,
-                    directory: s.directory,
-                    created_at: s.created_at,
-                })
-                .collect())
-        }
+        Ok(sessions) => Ok(sessions.into_iter().map(Into::into).collect()),
         Err(e) => Err(ServerFnError::new(e.to_string())),
     }
 }
 
-/// Create a new session
+/// Create a new session, optionally with the client's terminal size
+///
+/// An initial prompt is typed into the session by the server as soon as it
+/// is ready, so the task cannot be lost between creating and attaching.
 #[server]
-pub async fn create_session(name: String, directory: String) -> Result<String, ServerFnError> {
+pub async fn create_session(
+    name: String,
+    directory: String,
+    size: Option<crate::models::session::TerminalSize>,
+    initial_prompt: Option<String>,
+) -> Result<String, ServerFnError> {
     use crate::models::session::SessionConfig;
 
     let app_state = crate::AppState::global();
-    let session_config = SessionConfig { name, directory };
+    let session_config = SessionConfig {
+        name,
+        directory,
+        size,
+        initial_prompt,
+    };
 
     match app_state.session_manager.create_session(session_config).await {
         Ok(session_id) => {
@@ -108,14 +113,39 @@ pub async fn create_session(name: String, directory: String) -> Result<String, S
     }
 }
 
-/// Delete a session
+/// Delete a session, terminating its process tree
+///
+/// Reports whether every process exited within the grace period.
 #[server]
-pub async fn delete_session(session_id: String) -> Result<(), ServerFnError> {
+pub async fn delete_session(
+    session_id: String,
+) -> Result<crate::models::session::SessionShutdown, ServerFnError> {
     let app_state = crate::AppState::global();
 
     match app_state.session_manager.close_session(&session_id).await {
-        Ok(()) => {
+        Ok(shutdown) => {
             tracing::info!("Session deleted via REST API: {}", session_id);
+            Ok(shutdown)
+        }
+        Err(e) => Err(ServerFnError::new(e.to_string())),
+    }
+}
+
+/// Interrupt, suspend, resume or send EOF to a session's foreground job
+#[server]
+pub async fn signal_session(
+    session_id: String,
+    signal: crate::models::session::SessionSignal,
+) -> Result<(), ServerFnError> {
+    let app_state = crate::AppState::global();
+
+    match app_state
+        .session_manager
+        .signal_session(&session_id, signal)
+        .await
+    {
+        Ok(()) => {
+            tracing::info!("Sent {:?} to session {} via REST API", signal, session_id);
             Ok(())
         }
         Err(e) => Err(ServerFnError::new(e.to_string())),
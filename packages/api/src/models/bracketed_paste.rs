@@ -0,0 +1,117 @@
+//! Bracketed paste: text framed so the child reads it as one paste
+//!
+//! Programs that turn the mode on with `ESC [ ? 2004 h` take everything
+//! between `ESC [ 200 ~` and `ESC [ 201 ~` as pasted, so newlines inside it
+//! are part of the text instead of submitting it line by line.
+
+/// DEC private mode number for bracketed paste
+const MODE: &[u8] = b"2004";
+
+/// Marks the start of pasted text
+pub const PASTE_START: &str = "\x1b[200~";
+
+/// Marks the end of pasted text
+pub const PASTE_END: &str = "\x1b[201~";
+
+/// Longest mode sequence held back while waiting for the rest of it
+const MAX_SEQUENCE_LEN: usize = 32;
+
+/// Follows whether the child has bracketed paste enabled, from its output
+///
+/// Mode switches may be split across reads, so the start of an unfinished
+/// escape sequence is kept until the next chunk.
+#[derive(Debug, Clone, Default)]
+pub struct PasteMode {
+    enabled: bool,
+    /// Unfinished escape sequence, starting with ESC
+    partial: Vec<u8>,
+}
+
+impl PasteMode {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Look for mode switches in the next chunk of output
+    pub fn feed(&mut self, output: &[u8]) {
+        for &byte in output {
+            if byte == 0x1b {
+                self.partial.clear();
+                self.partial.push(byte);
+            } else if !self.partial.is_empty() {
+                self.partial.push(byte);
+                self.advance();
+            }
+        }
+    }
+
+    /// Check the sequence so far, dropping it once it cannot be a mode switch
+    fn advance(&mut self) {
+        let sequence = self.partial.as_slice();
+        let keep = match sequence {
+            [_, b'['] | [_, b'[', b'?'] => true,
+            [_, b'[', b'?', params @ .., last] => match last {
+                b'0'..=b'9' | b';' => sequence.len() < MAX_SEQUENCE_LEN,
+                b'h' | b'l' => {
+                    // Several modes can be switched at once, as in `?1049;2004h`
+                    if params.split(|&b| b == b';').any(|param| param == MODE) {
+                        self.enabled = *last == b'h';
+                    }
+                    false
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        if !keep {
+            self.partial.clear();
+        }
+    }
+}
+
+/// Wrap text in paste markers
+///
+/// Escape characters inside the text are dropped: without them no end
+/// marker, however it is nested, can end the paste early and have the
+/// rest taken as typed keys.
+pub fn frame_paste(text: &str) -> String {
+    format!("{}{}{}", PASTE_START, text.replace('\x1b', ""), PASTE_END)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_follows_switches_split_across_reads() {
+        let mut mode = PasteMode::default();
+        assert!(!mode.enabled());
+
+        mode.feed(b"prompt \x1b[?20");
+        assert!(!mode.enabled());
+        mode.feed(b"04h> ");
+        assert!(mode.enabled());
+
+        // Other modes leave it alone, combined switches count
+        mode.feed(b"\x1b[?25l\x1b[2004l");
+        assert!(mode.enabled());
+        mode.feed(b"\x1b[?1049;2004l");
+        assert!(!mode.enabled());
+    }
+
+    #[test]
+    fn test_frame_paste_cannot_be_escaped() {
+        assert_eq!(
+            frame_paste("line one\nline two"),
+            "\x1b[200~line one\nline two\x1b[201~"
+        );
+        assert_eq!(
+            frame_paste("a\x1b[201~\rrm -rf ~"),
+            "\x1b[200~a[201~\rrm -rf ~\x1b[201~"
+        );
+        assert_eq!(
+            frame_paste("\x1b[20\x1b[201~1~\rrm -rf ~"),
+            "\x1b[200~[20[201~1~\rrm -rf ~\x1b[201~"
+        );
+    }
+}
@@ -1,26 +1,151 @@
+use super::session::{SessionSignal, SessionStatus, TerminalSize};
 use serde::{Deserialize, Serialize};
 
+/// Version of the WebSocket protocol spoken by this build
+///
+/// Bumped whenever a change to `ClientEvent` or `ServerEvent` would confuse
+/// an older peer. Version 2 made `SendInput` a paste that no longer submits.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version this build still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
 /// Events sent FROM client TO server via WebSocket
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", content = "data")]
 pub enum ClientEvent {
+    /// Announce the client's protocol version and what it supports; the
+    /// server answers with `Welcome` or a `ProtocolError`
+    ///
+    /// Clients that never say hello are assumed to speak version 1.
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<Capability>,
+    },
+
     /// Create a new terminal session
     CreateSession { name: String, directory: String },
 
-    /// Send input to a terminal session
+    /// Type text into a terminal session without submitting it
+    ///
+    /// The text arrives as a single paste when the session's program has
+    /// enabled bracketed paste, so embedded newlines stay in the text.
+    /// For version 1 clients, which expect their input to be submitted, the
+    /// server presses Enter after it.
     SendInput { session_id: String, input: String },
 
-    /// Request session history (buffered output)
+    /// Send keystrokes to a terminal session, already translated to the
+    /// bytes a terminal would produce (control characters and escape
+    /// sequences) and written exactly as given
+    SendKeys { session_id: String, keys: String },
+
+    /// Interrupt, suspend, resume or send EOF to a session's foreground job
+    Signal {
+        session_id: String,
+        signal: SessionSignal,
+    },
+
+    /// Resize a session's terminal to the client's character grid
+    Resize {
+        session_id: String,
+        size: TerminalSize,
+    },
+
+    /// Receive a session's output: its history snapshot, then live output
+    ///
+    /// With `after`, a client that already has the output up to that stream
+    /// offset only receives what followed, as regular output. If that is no
+    /// longer buffered it gets the full history snapshot instead.
+    Subscribe {
+        session_id: String,
+        #[serde(default)]
+        after: Option<u64>,
+    },
+
+    /// Stop receiving a session's output
+    Unsubscribe { session_id: String },
+
+    /// Request session history (the current screen and its scrollback),
+    /// subscribing like `Subscribe`
     RequestHistory { session_id: String },
 
+    /// Choose how terminal output is delivered on this socket
+    SetOutputEncoding { encoding: OutputEncoding },
+
     /// Close a session
     CloseSession { session_id: String },
+
+    /// Heartbeat; the server answers with `Pong`
+    Ping { nonce: u64 },
+
+    /// Answer to the server's `Ping`
+    Pong { nonce: u64 },
+}
+
+impl ClientEvent {
+    /// Parse a client message, explaining what was wrong with it if it is
+    /// not an event this build understands
+    pub fn decode(payload: &[u8]) -> Result<Self, ProtocolError> {
+        let value: serde_json::Value = serde_json::from_slice(payload)
+            .map_err(|e| ProtocolError::malformed(None, e.to_string()))?;
+        let event_type = value
+            .get("type")
+            .and_then(|event_type| event_type.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| ProtocolError::malformed(None, "missing event type".to_string()))?;
+
+        let tag = serde_json::Value::String(event_type.clone());
+        if serde_json::from_value(tag).ok() == Some(ClientEventType::Unknown) {
+            return Err(ProtocolError {
+                kind: ProtocolErrorKind::UnknownEvent,
+                message: format!("Unknown event type {}", event_type),
+                event_type: Some(event_type),
+            });
+        }
+
+        serde_json::from_value(value)
+            .map_err(|e| ProtocolError::malformed(Some(event_type), e.to_string()))
+    }
+}
+
+/// The `type` tag of each `ClientEvent`, without its data
+///
+/// A test matches every event to its tag here, so the two cannot drift
+/// apart.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+enum ClientEventType {
+    Hello,
+    CreateSession,
+    SendInput,
+    SendKeys,
+    Signal,
+    Resize,
+    Subscribe,
+    Unsubscribe,
+    RequestHistory,
+    SetOutputEncoding,
+    CloseSession,
+    Ping,
+    Pong,
+    #[serde(other)]
+    Unknown,
 }
 
 /// Events sent FROM server TO client via WebSocket
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", content = "data")]
 pub enum ServerEvent {
+    /// Answer to `Hello`: the protocol version both sides will speak, which
+    /// is never newer than the client's, and what the server supports
+    Welcome {
+        protocol_version: u32,
+        server_version: String,
+        capabilities: Vec<Capability>,
+        /// The server's heartbeat schedule, for the client to keep to
+        heartbeat: HeartbeatSettings,
+    },
+
     /// Session was created successfully
     SessionCreated {
         session_id: String,
@@ -28,13 +153,52 @@ pub enum ServerEvent {
         directory: String,
     },
 
-    /// List of all active sessions (sent on connect)
+    /// List of all sessions, replacing the client's (sent on connect and
+    /// whenever the client missed updates)
     SessionList { sessions: Vec<SessionInfo> },
 
+    /// A session joined the list, created by this client or any other
+    SessionAdded { session: SessionInfo },
+
+    /// A session was closed and left the list
+    SessionRemoved { session_id: String },
+
+    /// A session's lifecycle status changed
+    SessionStatusChanged {
+        session_id: String,
+        status: SessionStatus,
+    },
+
     /// Terminal output data
-    TerminalOutput { session_id: String, data: String },
+    ///
+    /// `offset` is the session's output stream offset just past `data`, the
+    /// point to resume from after a reconnect.
+    TerminalOutput {
+        session_id: String,
+        data: String,
+        offset: u64,
+    },
 
-    /// Session was closed
+    /// The session's screen and its scrollback, replacing whatever the
+    /// client had for the session
+    SessionHistory {
+        session_id: String,
+        data: String,
+        offset: u64,
+    },
+
+    /// The client fell too far behind on a session's output, which has
+    /// stopped; it should request history to catch up
+    ResyncNeeded { session_id: String },
+
+    /// The session has shown its first prompt; input sent now is not lost
+    SessionReady { session_id: String },
+
+    /// Confirms the encoding used for terminal output from here on
+    OutputEncodingChanged { encoding: OutputEncoding },
+
+    /// The process of an attached session ended, or the session was
+    /// closed; an exited session stays listed until it is closed
     SessionClosed {
         session_id: String,
         reason: Option<String>,
@@ -42,6 +206,156 @@ pub enum ServerEvent {
 
     /// Error occurred
     Error { message: String },
+
+    /// A client message was rejected without being acted on
+    ProtocolError(ProtocolError),
+
+    /// Heartbeat; clients answer with `Pong` or get disconnected
+    Ping { nonce: u64 },
+
+    /// Answer to the client's `Ping`
+    Pong { nonce: u64 },
+}
+
+/// How often pings are sent and how long a silent peer is given before its
+/// connection is dropped
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeartbeatSettings {
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+}
+
+/// Optional protocol features a peer supports
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Capability {
+    /// Output as binary `OutputFrame`s
+    BinaryOutput,
+
+    /// `Subscribe` with `after` to resume from a stream offset
+    Resume,
+
+    /// `Ping`/`Pong` heartbeats
+    Heartbeat,
+
+    /// `ResyncNeeded` for clients that fall behind
+    Resync,
+
+    /// `SendKeys` for raw keystrokes
+    RawKeys,
+
+    /// `Signal` for job control
+    Signals,
+
+    /// Announced by a newer peer, not known to this build
+    #[serde(other)]
+    Unknown,
+}
+
+/// Why a client message was rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProtocolError {
+    pub kind: ProtocolErrorKind,
+    /// The `type` of the rejected event, when there was one
+    pub event_type: Option<String>,
+    pub message: String,
+}
+
+impl ProtocolError {
+    fn malformed(event_type: Option<String>, message: String) -> Self {
+        Self {
+            kind: ProtocolErrorKind::MalformedEvent,
+            event_type,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ProtocolErrorKind {
+    /// The client's protocol version is too old for this server
+    UnsupportedVersion,
+
+    /// The event type is not one this server knows, likely from a newer client
+    UnknownEvent,
+
+    /// Not valid JSON, or the event's data does not fit its type
+    MalformedEvent,
+}
+
+/// How `TerminalOutput` and `SessionHistory` reach the client
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// JSON events with output decoded to UTF-8 on the server
+    #[default]
+    Text,
+
+    /// Raw PTY bytes in binary `OutputFrame`s, decoded by the client
+    Binary,
+}
+
+/// What a binary output frame carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// Live output, appended to what the client has
+    Output = 1,
+
+    /// Screen snapshot, replacing what the client has
+    History = 2,
+}
+
+/// Raw terminal output sent as a binary WebSocket message
+///
+/// Layout: kind (1 byte), session id length (u16, big endian), session id,
+/// stream offset just past the data (u64, big endian), then the output
+/// bytes. The leading kind byte never starts a JSON document, so frames can
+/// share the socket with JSON encoded events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFrame {
+    pub kind: FrameKind,
+    pub session_id: String,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+impl OutputFrame {
+    const HEADER_LEN: usize = 3;
+    const OFFSET_LEN: usize = 8;
+
+    pub fn encode(&self) -> Vec<u8> {
+        let id = self.session_id.as_bytes();
+        let id_len = u16::try_from(id.len()).unwrap_or(u16::MAX);
+        let id = &id[..id_len as usize];
+
+        let mut frame =
+            Vec::with_capacity(Self::HEADER_LEN + id.len() + Self::OFFSET_LEN + self.data.len());
+        frame.push(self.kind as u8);
+        frame.extend_from_slice(&id_len.to_be_bytes());
+        frame.extend_from_slice(id);
+        frame.extend_from_slice(&self.offset.to_be_bytes());
+        frame.extend_from_slice(&self.data);
+        frame
+    }
+
+    /// Parse a binary message, or `None` if it is not an output frame
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let kind = match *frame.first()? {
+            1 => FrameKind::Output,
+            2 => FrameKind::History,
+            _ => return None,
+        };
+        let id_len = u16::from_be_bytes([*frame.get(1)?, *frame.get(2)?]) as usize;
+        let id_end = Self::HEADER_LEN.checked_add(id_len)?;
+        let session_id = std::str::from_utf8(frame.get(Self::HEADER_LEN..id_end)?).ok()?;
+        let data_start = id_end + Self::OFFSET_LEN;
+        let offset = u64::from_be_bytes(frame.get(id_end..data_start)?.try_into().ok()?);
+
+        Some(Self {
+            kind,
+            session_id: session_id.to_string(),
+            offset,
+            data: frame[data_start..].to_vec(),
+        })
+    }
 }
 
 /// Lightweight session information
@@ -51,6 +365,20 @@ pub struct SessionInfo {
     pub name: String,
     pub directory: String,
     pub created_at: String, // ISO 8601 timestamp
+    #[serde(default)]
+    pub status: SessionStatus,
+}
+
+impl From<super::session::SessionInfo> for SessionInfo {
+    fn from(info: super::session::SessionInfo) -> Self {
+        Self {
+            id: info.id,
+            name: info.name,
+            directory: info.directory,
+            created_at: info.created_at,
+            status: info.status,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -70,11 +398,122 @@ mod tests {
         assert_eq!(event, deserialized);
     }
 
+    #[test]
+    fn test_client_event_decode_explains_rejections() {
+        let hello = br#"{"type":"Hello","data":{"protocol_version":1,"capabilities":["Resume","Teleport"]}}"#;
+        assert_eq!(
+            ClientEvent::decode(hello),
+            Ok(ClientEvent::Hello {
+                protocol_version: 1,
+                capabilities: vec![Capability::Resume, Capability::Unknown],
+            })
+        );
+
+        let unknown = ClientEvent::decode(br#"{"type":"Teleport","data":{"to":"mars"}}"#);
+        assert_eq!(
+            unknown.map_err(|e| (e.kind, e.event_type)),
+            Err((ProtocolErrorKind::UnknownEvent, Some("Teleport".into())))
+        );
+
+        let malformed = ClientEvent::decode(br#"{"type":"Ping","data":{}}"#);
+        assert_eq!(
+            malformed.map_err(|e| (e.kind, e.event_type)),
+            Err((ProtocolErrorKind::MalformedEvent, Some("Ping".into())))
+        );
+
+        let garbage = ClientEvent::decode(b"not json");
+        assert_eq!(
+            garbage.map_err(|e| (e.kind, e.event_type)),
+            Err((ProtocolErrorKind::MalformedEvent, None))
+        );
+
+        // Bad data for a known type, even an unknown variant within it, is
+        // not an unknown event
+        let bad_signal = ClientEvent::decode(
+            br#"{"type":"Signal","data":{"session_id":"s","signal":"Signal"}}"#,
+        );
+        assert_eq!(
+            bad_signal.map_err(|e| (e.kind, e.event_type)),
+            Err((ProtocolErrorKind::MalformedEvent, Some("Signal".into())))
+        );
+    }
+
+    /// Fails to compile when an event is added without its tag
+    fn event_type(event: &ClientEvent) -> ClientEventType {
+        match event {
+            ClientEvent::Hello { .. } => ClientEventType::Hello,
+            ClientEvent::CreateSession { .. } => ClientEventType::CreateSession,
+            ClientEvent::SendInput { .. } => ClientEventType::SendInput,
+            ClientEvent::SendKeys { .. } => ClientEventType::SendKeys,
+            ClientEvent::Signal { .. } => ClientEventType::Signal,
+            ClientEvent::Resize { .. } => ClientEventType::Resize,
+            ClientEvent::Subscribe { .. } => ClientEventType::Subscribe,
+            ClientEvent::Unsubscribe { .. } => ClientEventType::Unsubscribe,
+            ClientEvent::RequestHistory { .. } => ClientEventType::RequestHistory,
+            ClientEvent::SetOutputEncoding { .. } => ClientEventType::SetOutputEncoding,
+            ClientEvent::CloseSession { .. } => ClientEventType::CloseSession,
+            ClientEvent::Ping { .. } => ClientEventType::Ping,
+            ClientEvent::Pong { .. } => ClientEventType::Pong,
+        }
+    }
+
+    #[test]
+    fn test_every_client_event_has_its_tag() {
+        let id = || "s".to_string();
+        let events = [
+            ClientEvent::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: vec![],
+            },
+            ClientEvent::CreateSession {
+                name: "test".into(),
+                directory: "/tmp".into(),
+            },
+            ClientEvent::SendInput {
+                session_id: id(),
+                input: "hi".into(),
+            },
+            ClientEvent::SendKeys {
+                session_id: id(),
+                keys: "\x03".into(),
+            },
+            ClientEvent::Signal {
+                session_id: id(),
+                signal: SessionSignal::Interrupt,
+            },
+            ClientEvent::Resize {
+                session_id: id(),
+                size: TerminalSize::new(24, 80),
+            },
+            ClientEvent::Subscribe {
+                session_id: id(),
+                after: Some(1),
+            },
+            ClientEvent::Unsubscribe { session_id: id() },
+            ClientEvent::RequestHistory { session_id: id() },
+            ClientEvent::SetOutputEncoding {
+                encoding: OutputEncoding::Binary,
+            },
+            ClientEvent::CloseSession { session_id: id() },
+            ClientEvent::Ping { nonce: 1 },
+            ClientEvent::Pong { nonce: 1 },
+        ];
+
+        for event in events {
+            let payload = serde_json::to_vec(&event).unwrap();
+            let tag: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            let tag: ClientEventType = serde_json::from_value(tag["type"].clone()).unwrap();
+            assert_eq!(tag, event_type(&event));
+            assert_eq!(ClientEvent::decode(&payload), Ok(event));
+        }
+    }
+
     #[test]
     fn test_server_event_serialization() {
         let event = ServerEvent::TerminalOutput {
             session_id: "123".into(),
             data: "Hello\n".into(),
+            offset: 6,
         };
 
         let json = serde_json::to_string(&event).unwrap();
@@ -82,4 +521,31 @@ mod tests {
 
         assert_eq!(event, deserialized);
     }
+
+    #[test]
+    fn test_output_frame_round_trip() {
+        let frame = OutputFrame {
+            kind: FrameKind::Output,
+            session_id: "123".into(),
+            offset: 1 << 40,
+            // Half of a multi-byte character must survive untouched
+            data: vec![b'a', 0xf0, 0x9f],
+        };
+
+        let encoded = frame.encode();
+        assert_eq!(OutputFrame::decode(&encoded), Some(frame));
+    }
+
+    #[test]
+    fn test_output_frame_rejects_json() {
+        let event = ServerEvent::Error {
+            message: "boom".into(),
+        };
+        let json = serde_json::to_vec(&event).unwrap();
+
+        assert_eq!(OutputFrame::decode(&json), None);
+        assert_eq!(OutputFrame::decode(&[1, 0]), None);
+        // Header without the offset
+        assert_eq!(OutputFrame::decode(&[1, 0, 1, b's', 0, 0]), None);
+    }
 }
@@ -1,2 +1,9 @@
+#[cfg(not(target_family = "wasm"))]
+pub mod bracketed_paste;
 pub mod message;
+#[cfg(not(target_family = "wasm"))]
+pub mod screen;
+#[cfg(not(target_family = "wasm"))]
+pub mod scrollback;
 pub mod session;
+pub mod utf8;
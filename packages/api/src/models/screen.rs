@@ -0,0 +1,270 @@
+//! Headless terminal screen kept for each session
+
+use super::session::TerminalSize;
+
+/// Rows of scrollback each session's screen keeps by default
+pub const DEFAULT_SCREEN_SCROLLBACK_LINES: usize = 1000;
+
+/// A session's terminal as the program's output has drawn it
+///
+/// Interactive programs redraw their interface with cursor movement, so
+/// replaying part of the raw output leaves a garbled screen. Clients that
+/// join late get the screen as it stands, plus the rows that scrolled off
+/// the top of it.
+pub struct Screen {
+    parser: vt100::Parser,
+}
+
+impl Screen {
+    pub fn new(size: TerminalSize, scrollback_lines: usize) -> Self {
+        Self {
+            parser: vt100::Parser::new(size.rows, size.cols, scrollback_lines),
+        }
+    }
+
+    pub fn process(&mut self, output: &[u8]) {
+        self.parser.process(output);
+    }
+
+    pub fn resize(&mut self, size: TerminalSize) {
+        self.parser.screen_mut().set_size(size.rows, size.cols);
+    }
+
+    /// Scrollback followed by the visible screen, one line per row
+    ///
+    /// Rows are written as text and SGR sequences only, which every client
+    /// renderer understands, rather than the erases and cursor jumps vt100
+    /// would use. They carry their own colors and attributes and reset them
+    /// at the end, so each one renders the same on its own. Blank rows below
+    /// the cursor are left out. Full screen programs on the alternate screen
+    /// have no scrollback.
+    ///
+    /// The snapshot ends by moving the cursor back to where the program
+    /// left it and restoring the attributes it was drawing with, so output
+    /// following it lands the same as on the real screen.
+    pub fn snapshot(&mut self) -> Vec<u8> {
+        let (rows, cols) = self.parser.screen().size();
+        let mut lines = Vec::new();
+
+        if !self.parser.screen().alternate_screen() {
+            // Scrollback is only reachable by scrolling the view back
+            self.parser.screen_mut().set_scrollback(usize::MAX);
+            let scrollback = self.parser.screen().scrollback();
+            let mut line = 0;
+            while line < scrollback {
+                self.parser.screen_mut().set_scrollback(scrollback - line);
+                let page = (scrollback - line).min(rows as usize);
+                let screen = self.parser.screen();
+                lines.extend((0..page as u16).map(|row| formatted_row(screen, row, cols)));
+                line += page;
+            }
+            self.parser.screen_mut().set_scrollback(0);
+        }
+
+        let screen = self.parser.screen();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let mut visible: Vec<Vec<u8>> = (0..rows)
+            .map(|row| formatted_row(screen, row, cols))
+            .collect();
+        let used_rows = visible
+            .iter()
+            .rposition(|row| !row.is_empty())
+            .map_or(0, |index| index + 1)
+            .max(cursor_row as usize + 1);
+        visible.truncate(used_rows);
+        lines.extend(visible);
+
+        let mut snapshot = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                snapshot.extend_from_slice(b"\r\n");
+            }
+            snapshot.extend_from_slice(line);
+        }
+
+        // Writing the rows left the cursor at the end of the last one
+        let rows_up = used_rows - 1 - cursor_row as usize;
+        if rows_up > 0 {
+            snapshot.extend_from_slice(format!("\x1b[{}A", rows_up).as_bytes());
+        }
+        snapshot.extend_from_slice(format!("\x1b[{}G", cursor_col + 1).as_bytes());
+        let attributes = screen.attributes_formatted();
+        if attributes != b"\x1b[m" {
+            snapshot.extend_from_slice(&attributes);
+        }
+        snapshot
+    }
+}
+
+/// One row of the visible screen as text, with an SGR sequence wherever
+/// the attributes change and a reset at the end if any were set
+///
+/// Trailing blanks are left out unless they show something, such as a
+/// background fill.
+fn formatted_row(screen: &vt100::Screen, row: u16, cols: u16) -> Vec<u8> {
+    let cells: Vec<&vt100::Cell> = (0..cols).filter_map(|col| screen.cell(row, col)).collect();
+    let len = cells
+        .iter()
+        .rposition(|cell| cell.has_contents() || shows_blank(cell))
+        .map_or(0, |index| index + 1);
+
+    let mut line = Vec::new();
+    let mut current = String::new();
+    for cell in &cells[..len] {
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let params = sgr_params(cell);
+        if params != current {
+            line.extend_from_slice(format!("\x1b[0{}m", params).as_bytes());
+            current = params;
+        }
+        if cell.has_contents() {
+            line.extend_from_slice(cell.contents().as_bytes());
+        } else {
+            line.push(b' ');
+        }
+    }
+    if !current.is_empty() {
+        line.extend_from_slice(b"\x1b[m");
+    }
+    line
+}
+
+/// Whether a blank cell looks different from an empty one
+fn shows_blank(cell: &vt100::Cell) -> bool {
+    cell.bgcolor() != vt100::Color::Default || cell.inverse() || cell.underline()
+}
+
+/// SGR parameters for a cell's attributes, each preceded by `;`, empty for
+/// the default ones
+fn sgr_params(cell: &vt100::Cell) -> String {
+    let mut params = String::new();
+    for (set, code) in [
+        (cell.bold(), 1),
+        (cell.dim(), 2),
+        (cell.italic(), 3),
+        (cell.underline(), 4),
+        (cell.inverse(), 7),
+    ] {
+        if set {
+            params.push_str(&format!(";{}", code));
+        }
+    }
+    push_color(&mut params, cell.fgcolor(), 30);
+    push_color(&mut params, cell.bgcolor(), 40);
+    params
+}
+
+/// Color parameters based at 30 for the foreground or 40 for the background
+fn push_color(params: &mut String, color: vt100::Color, base: u8) {
+    let color = match color {
+        vt100::Color::Default => return,
+        vt100::Color::Idx(index @ 0..=7) => format!("{}", base + index),
+        vt100::Color::Idx(index @ 8..=15) => format!("{}", base + 60 + index - 8),
+        vt100::Color::Idx(index) => format!("{};5;{}", base + 8, index),
+        vt100::Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+    };
+    params.push(';');
+    params.push_str(&color);
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new(TerminalSize::default(), DEFAULT_SCREEN_SCROLLBACK_LINES)
+    }
+}
+
+impl std::fmt::Debug for Screen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Screen")
+            .field("size", &self.parser.screen().size())
+            .field("alternate", &self.parser.screen().alternate_screen())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_shows_redrawn_screen_after_scrollback() {
+        let mut screen = Screen::new(TerminalSize::new(3, 20), 2);
+        for line in 0..5 {
+            screen.process(format!("line {}\r\n", line).as_bytes());
+        }
+        // Redraw the last line in place, as progress output does
+        screen.process(b"working 10%\r\x1b[2Kworking \x1b[1mdone");
+
+        let snapshot = String::from_utf8(screen.snapshot()).unwrap();
+        // Only two rows of scrollback are kept
+        assert_eq!(
+            snapshot,
+            "line 1\r\nline 2\r\nline 3\r\nline 4\r\nworking \x1b[0;1mdone\x1b[m\x1b[13G\x1b[m\x1b[1m"
+        );
+    }
+
+    #[test]
+    fn test_snapshot_leaves_out_blank_rows_and_follows_resizes() {
+        let mut screen = Screen::new(TerminalSize::new(10, 20), 100);
+        screen.process(b"\x1b[2J\x1b[Hprompt> ");
+        assert_eq!(screen.snapshot(), b"prompt> \x1b[9G");
+
+        screen.resize(TerminalSize::new(10, 4));
+        assert_eq!(screen.snapshot(), b"prom\x1b[4G");
+    }
+
+    /// Final bytes of the control sequences in `output`
+    fn control_sequences(output: &[u8]) -> Vec<char> {
+        let text = String::from_utf8_lossy(output);
+        text.split("\x1b[")
+            .skip(1)
+            .filter_map(|sequence| sequence.chars().find(|c| c.is_ascii_alphabetic()))
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_only_uses_sequences_clients_render() {
+        let mut screen = Screen::new(TerminalSize::new(4, 10), 100);
+        // A status bar filled with a background color, then a row that wraps
+        screen.process(b"\x1b[44m\x1b[K\x1b[1;97mok\x1b[0m\r\n");
+        screen.process(b"a long line that wraps\x1b[38;5;208m!");
+
+        let snapshot = screen.snapshot();
+        assert!(control_sequences(&snapshot)
+            .iter()
+            .all(|c| matches!(c, 'm' | 'A' | 'G')));
+        let snapshot = String::from_utf8(snapshot).unwrap();
+        assert!(snapshot.starts_with("\x1b[0;1;97;44mok\x1b[0;44m        \x1b[m\r\n"));
+        assert!(snapshot.contains("a long lin\r\ne that wra\r\nps\x1b[0;38;5;208m!\x1b[m"));
+    }
+
+    #[test]
+    fn test_alternate_screen_has_no_scrollback() {
+        let mut screen = Screen::new(TerminalSize::new(2, 20), 100);
+        screen.process(b"one\r\ntwo\r\nthree\r\n");
+        screen.process(b"\x1b[?1049h\x1b[Hfull screen");
+
+        assert_eq!(screen.snapshot(), b"full screen\x1b[12G");
+    }
+
+    #[test]
+    fn test_output_after_snapshot_lands_in_place() {
+        let size = TerminalSize::new(5, 20);
+        let mut screen = Screen::new(size, 100);
+        // Attach while a progress display is halfway through redrawing
+        screen.process(b"a: 10%\r\nb: 10%\r\nc: 10%\r\n\r\n\x1b[4A\x1b[4G\x1b[32m");
+        let mut attached = Screen::new(size, 100);
+        attached.process(&screen.snapshot());
+
+        let rest = b"50%\x1b[B\x1b[4G75%";
+        screen.process(rest);
+        attached.process(rest);
+
+        let (expected, actual) = (screen.parser.screen(), attached.parser.screen());
+        assert_eq!(actual.contents(), expected.contents());
+        assert_eq!(actual.cursor_position(), expected.cursor_position());
+        assert_eq!(actual.contents_formatted(), expected.contents_formatted());
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+
+/// Default scrollback kept per session (1 MiB)
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 1024 * 1024;
+
+/// Terminal output history capped at a fixed number of bytes
+///
+/// Output is stored as the raw chunks it arrived in. Once the limit is
+/// exceeded the oldest chunks are dropped, so memory use stays bounded no
+/// matter how long a session runs.
+///
+/// Every byte has an offset in the session's output stream, counted from
+/// the first byte ever pushed, so a reader can ask for what came after the
+/// last byte it saw.
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    chunks: VecDeque<Vec<u8>>,
+    len: usize,
+    limit: usize,
+    /// Offset just past the newest byte, evicted output included
+    end: u64,
+}
+
+impl Scrollback {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            len: 0,
+            limit,
+            end: 0,
+        }
+    }
+
+    /// Append output, evicting the oldest chunks beyond the byte limit
+    pub fn push(&mut self, mut chunk: Vec<u8>) {
+        self.end += chunk.len() as u64;
+        if chunk.len() > self.limit {
+            // Only the tail of an oversized chunk can ever be replayed
+            chunk.drain(..chunk.len() - self.limit);
+        }
+
+        self.len += chunk.len();
+        self.chunks.push_back(chunk);
+
+        let mut evicted_any = false;
+        while self.len > self.limit {
+            match self.chunks.pop_front() {
+                Some(evicted) => {
+                    self.len -= evicted.len();
+                    evicted_any = true;
+                }
+                None => break,
+            }
+        }
+
+        if evicted_any || self.chunks.len() == 1 {
+            self.trim_partial_character();
+        }
+    }
+
+    /// Drop continuation bytes left at the front by a cut through a character
+    ///
+    /// Reads split output anywhere, so after eviction the oldest chunk may
+    /// start halfway through a multi-byte UTF-8 sequence that can never be
+    /// completed.
+    fn trim_partial_character(&mut self) {
+        while let Some(front) = self.chunks.front_mut() {
+            let partial = front
+                .iter()
+                .take(3)
+                .take_while(|&&byte| byte & 0xc0 == 0x80)
+                .count();
+            front.drain(..partial);
+            self.len -= partial;
+
+            if !front.is_empty() {
+                break;
+            }
+            self.chunks.pop_front();
+        }
+    }
+
+    /// All retained output, oldest first
+    pub fn contents(&self) -> Vec<u8> {
+        let mut contents = Vec::with_capacity(self.len);
+        for chunk in &self.chunks {
+            contents.extend_from_slice(chunk);
+        }
+        contents
+    }
+
+    /// Everything after stream offset `offset`, or `None` once part of it
+    /// has been evicted or the offset lies in the future
+    pub fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.start_offset() || offset > self.end {
+            return None;
+        }
+
+        let mut skip = (offset - self.start_offset()) as usize;
+        let mut output = Vec::with_capacity(self.len - skip);
+        for chunk in &self.chunks {
+            if skip >= chunk.len() {
+                skip -= chunk.len();
+                continue;
+            }
+            output.extend_from_slice(&chunk[skip..]);
+            skip = 0;
+        }
+        Some(output)
+    }
+
+    /// Stream offset of the oldest retained byte
+    pub fn start_offset(&self) -> u64 {
+        self.end - self.len as u64
+    }
+
+    /// Stream offset just past the newest byte
+    pub fn end_offset(&self) -> u64 {
+        self.end
+    }
+
+    /// Number of bytes currently retained
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCROLLBACK_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrollback_keeps_output_under_limit() {
+        let mut scrollback = Scrollback::new(16);
+        scrollback.push(b"hello ".to_vec());
+        scrollback.push(b"world".to_vec());
+
+        assert_eq!(scrollback.contents(), b"hello world");
+        assert_eq!(scrollback.len(), 11);
+    }
+
+    #[test]
+    fn test_scrollback_evicts_oldest_chunks() {
+        let mut scrollback = Scrollback::new(10);
+        scrollback.push(b"aaaa".to_vec());
+        scrollback.push(b"bbbb".to_vec());
+        scrollback.push(b"cccc".to_vec());
+
+        assert_eq!(scrollback.contents(), b"bbbbcccc");
+        assert!(scrollback.len() <= scrollback.limit());
+    }
+
+    #[test]
+    fn test_scrollback_truncates_oversized_chunk() {
+        let mut scrollback = Scrollback::new(4);
+        scrollback.push(b"abcdefgh".to_vec());
+
+        assert_eq!(scrollback.contents(), b"efgh");
+    }
+
+    #[test]
+    fn test_scrollback_truncates_on_char_boundary() {
+        let mut scrollback = Scrollback::new(5);
+        // "é" is two bytes, so the cut has to move forward past it
+        scrollback.push("abéééé".as_bytes().to_vec());
+
+        assert_eq!(scrollback.contents(), "éé".as_bytes());
+        assert_eq!(scrollback.len(), 4);
+    }
+
+    #[test]
+    fn test_scrollback_resumes_after_offset() {
+        let mut scrollback = Scrollback::new(10);
+        scrollback.push(b"aaaa".to_vec());
+        scrollback.push(b"bbbb".to_vec());
+        assert_eq!(scrollback.since(2), Some(b"aabbbb".to_vec()));
+        assert_eq!(scrollback.since(8), Some(Vec::new()));
+        assert_eq!(scrollback.since(9), None);
+
+        // Evicting the first chunk moves the start but not the offsets
+        scrollback.push(b"cccc".to_vec());
+        assert_eq!(scrollback.start_offset(), 4);
+        assert_eq!(scrollback.end_offset(), 12);
+        assert_eq!(scrollback.since(6), Some(b"bbcccc".to_vec()));
+        assert_eq!(scrollback.since(2), None);
+    }
+
+    #[test]
+    fn test_scrollback_keeps_characters_split_across_chunks() {
+        let crab = "🦀".as_bytes();
+        let mut scrollback = Scrollback::new(16);
+        scrollback.push(crab[..2].to_vec());
+        scrollback.push(crab[2..].to_vec());
+
+        assert_eq!(scrollback.contents(), crab);
+    }
+}
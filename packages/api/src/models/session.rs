@@ -1,19 +1,133 @@
 #[cfg(not(target_family = "wasm"))]
+use super::bracketed_paste::PasteMode;
+#[cfg(not(target_family = "wasm"))]
+use super::screen::Screen;
+#[cfg(not(target_family = "wasm"))]
+use super::scrollback::Scrollback;
+#[cfg(not(target_family = "wasm"))]
+use crate::pty_io::{AsyncPty, PtyWriter};
+#[cfg(not(target_family = "wasm"))]
 use chrono::{DateTime, Utc};
 #[cfg(not(target_family = "wasm"))]
-use portable_pty::PtyPair;
+use portable_pty::{Child, ExitStatus, MasterPty};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+#[cfg(not(target_family = "wasm"))]
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(not(target_family = "wasm"))]
+use std::time::{Duration, Instant};
+#[cfg(not(target_family = "wasm"))]
+use tokio::sync::broadcast;
+
+/// Number of undelivered events a slow subscriber may fall behind by
+#[cfg(not(target_family = "wasm"))]
+const SESSION_EVENT_CAPACITY: usize = 1024;
+
+/// How recently a session must have produced output to count as running
+#[cfg(not(target_family = "wasm"))]
+const RUNNING_WINDOW: Duration = Duration::from_secs(2);
+
+/// How long the first output must settle before the session counts as ready
+#[cfg(not(target_family = "wasm"))]
+const READY_SETTLE: Duration = Duration::from_millis(500);
+
+/// Longest time output may keep coming after it started before the session
+/// is declared ready anyway
+#[cfg(not(target_family = "wasm"))]
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events published to everyone attached to a session
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// Raw bytes read from the session PTY, not necessarily valid UTF-8
+    ///
+    /// `offset` is the session's output stream offset just past `data`.
+    Output { offset: u64, data: Vec<u8> },
+
+    /// The process has drawn its first prompt and can take input
+    Ready,
+
+    /// The session process exited
+    Closed { reason: String },
+}
+
+/// Changes to the set of sessions, published by the session registry
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionChange {
+    /// A session was created and is listed from now on
+    Created(SessionInfo),
+
+    /// The session's process exited; it stays listed until closed
+    Exited { session_id: String, reason: String },
+
+    /// The session's lifecycle status changed
+    StatusChanged {
+        session_id: String,
+        status: SessionStatus,
+    },
+
+    /// The session was closed and removed
+    Closed { session_id: String },
+}
 
 /// Full session state stored on the server
+///
+/// Sessions are shared as `Arc<Session>`. Everything that changes while the
+/// session runs sits behind the session's own lock, so output from one
+/// session never contends with another.
 #[cfg(not(target_family = "wasm"))]
 pub struct Session {
     pub id: String,
     pub name: String,
     pub directory: String,
     pub created_at: DateTime<Utc>,
-    pub pty_pair: Option<Mutex<PtyPair>>,
-    pub output_buffer: Vec<String>,
+    pub pty_master: Option<Mutex<Box<dyn MasterPty + Send>>>,
+    /// Non-blocking I/O on the master, shared by the output pump and writers
+    pub pty: Option<Arc<AsyncPty>>,
+    /// Input waiting to be written to the PTY, in the order it was sent
+    pub input: Option<PtyWriter>,
+    pub child: Option<Mutex<Box<dyn Child + Send + Sync>>>,
+    pub events: broadcast::Sender<SessionEvent>,
+    state: Mutex<SessionState>,
+}
+
+/// What a client needs to join a session: its history so far, whether it
+/// is ready, and every event after that point
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug)]
+pub struct Attachment {
+    /// The screen with its scrollback, or only what followed the requested
+    /// offset
+    pub history: Vec<u8>,
+    /// Stream offset just past `history`
+    pub offset: u64,
+    /// Whether `history` continues from the requested offset rather than
+    /// replacing what the client has
+    pub resumed: bool,
+    pub ready: bool,
+    pub events: broadcast::Receiver<SessionEvent>,
+}
+
+/// The part of a session that changes while it runs
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug, Default)]
+struct SessionState {
+    output_buffer: Scrollback,
+    /// The terminal as the output so far has drawn it
+    screen: Screen,
+    exit_status: Option<ExitStatus>,
+    /// Whether attached clients were told about the exit
+    exit_announced: bool,
+    first_output_at: Option<Instant>,
+    last_output_at: Option<Instant>,
+    /// Status as last computed by `update_status`
+    status: SessionStatus,
+    ready: bool,
+    /// Process group stopped by `SessionSignal::Suspend`
+    suspended: Option<libc::pid_t>,
+    /// Whether the child wants typed text framed as a paste
+    paste_mode: PasteMode,
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -24,62 +138,281 @@ impl std::fmt::Debug for Session {
             .field("name", &self.name)
             .field("directory", &self.directory)
             .field("created_at", &self.created_at)
-            .field("pty_pair", &self.pty_pair.is_some())
-            .field("output_buffer", &self.output_buffer)
+            .field("pty_master", &self.pty_master.is_some())
+            .field("pty", &self.pty.is_some())
+            .field("input", &self.input.is_some())
+            .field("child", &self.child.is_some())
+            .field("state", &*self.state())
+            .field("subscribers", &self.events.receiver_count())
             .finish()
     }
 }
 
-/// Session metadata that can be safely shared across threads
-#[cfg(not(target_family = "wasm"))]
-#[derive(Clone, Debug)]
-pub struct SessionMetadata {
-    pub id: String,
-    pub name: String,
-    pub directory: String,
-    pub created_at: DateTime<Utc>,
-}
-
-#[cfg(not(target_family = "wasm"))]
-impl From<&Session> for SessionMetadata {
-    fn from(session: &Session) -> Self {
-        Self {
-            id: session.id.clone(),
-            name: session.name.clone(),
-            directory: session.directory.clone(),
-            created_at: session.created_at,
-        }
-    }
-}
-
 #[cfg(not(target_family = "wasm"))]
 impl Session {
     pub fn new(id: String, name: String, directory: String) -> Self {
+        let (events, _) = broadcast::channel(SESSION_EVENT_CAPACITY);
+
         Self {
             id,
             name,
             directory,
             created_at: Utc::now(),
-            pty_pair: None,
-            output_buffer: Vec::new(),
+            pty_master: None,
+            pty: None,
+            input: None,
+            child: None,
+            events,
+            state: Mutex::new(SessionState::default()),
         }
     }
 
+    /// Cap the session's scrollback at `limit` bytes
+    pub fn with_scrollback_limit(mut self, limit: usize) -> Self {
+        self.state_mut().output_buffer = Scrollback::new(limit);
+        self
+    }
+
+    /// Size the session's screen model to match its terminal, keeping
+    /// `scrollback_lines` rows that scroll off the top
+    pub fn with_screen(mut self, size: TerminalSize, scrollback_lines: usize) -> Self {
+        self.state_mut().screen = Screen::new(size, scrollback_lines);
+        self
+    }
+
+    /// Lock the session's mutable state
+    ///
+    /// Nothing panics while holding the lock with the state half updated,
+    /// so a poisoned lock is still safe to use.
+    fn state(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn state_mut(&mut self) -> &mut SessionState {
+        self.state.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
     pub fn to_info(&self) -> SessionInfo {
         SessionInfo {
             id: self.id.clone(),
             name: self.name.clone(),
             directory: self.directory.clone(),
             created_at: self.created_at.to_rfc3339(),
+            status: self.status(),
         }
     }
 
-    pub fn add_output(&mut self, output: String) {
-        self.output_buffer.push(output);
+    /// Status as of the last `update_status`
+    pub fn status(&self) -> SessionStatus {
+        self.state().status
     }
 
-    pub fn get_all_output(&self) -> Vec<String> {
-        self.output_buffer.clone()
+    /// Recompute the status from process state and output activity
+    ///
+    /// Returns the new status if it changed. A session that went quiet is
+    /// waiting for input until it has been quiet for `idle_after`.
+    pub fn update_status(&self, idle_after: Duration) -> Option<SessionStatus> {
+        let foreground_job = self.foreground_job_running();
+        let mut state = self.state();
+
+        let status = if state.exit_status.is_some() {
+            SessionStatus::Exited
+        } else if state.suspended.is_some() {
+            SessionStatus::Paused
+        } else if foreground_job {
+            SessionStatus::Running
+        } else {
+            match state.last_output_at.map(|at| at.elapsed()) {
+                None => SessionStatus::Starting,
+                Some(quiet) if quiet < RUNNING_WINDOW => SessionStatus::Running,
+                Some(quiet) if quiet < idle_after => SessionStatus::WaitingForInput,
+                Some(_) => SessionStatus::Idle,
+            }
+        };
+
+        if status == state.status {
+            return None;
+        }
+        state.status = status;
+        Some(status)
+    }
+
+    /// Announce readiness once the first output has settled
+    ///
+    /// Interactive programs draw their prompt and then wait, so a pause after
+    /// the first output means input will no longer be lost. Output that never
+    /// pauses, like an animation, is declared ready after a timeout; a program
+    /// that has printed nothing yet is still starting, however slowly, and is
+    /// not. Returns true when the session just became ready.
+    pub fn update_ready(&self) -> bool {
+        let mut state = self.state();
+        if state.ready || state.exit_status.is_some() {
+            return false;
+        }
+        let Some(first_output_at) = state.first_output_at else {
+            return false;
+        };
+
+        let settled = state
+            .last_output_at
+            .is_some_and(|at| at.elapsed() >= READY_SETTLE);
+        if !settled && first_output_at.elapsed() < READY_TIMEOUT {
+            return false;
+        }
+
+        state.ready = true;
+        let _ = self.events.send(SessionEvent::Ready);
+        true
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state().ready
+    }
+
+    /// Whether a job other than the session's own process holds the terminal
+    ///
+    /// A shell running `cargo test` in the foreground is busy even while the
+    /// tests print nothing.
+    fn foreground_job_running(&self) -> bool {
+        match (self.child_pid(), self.foreground_process_group()) {
+            (Some(pid), Some(pgid)) => pgid > 0 && pgid != pid,
+            _ => false,
+        }
+    }
+
+    /// Process id of the session's main process, which also leads its own
+    /// process group
+    pub fn child_pid(&self) -> Option<libc::pid_t> {
+        self.child
+            .as_ref()
+            .and_then(|child| child.lock().ok()?.process_id())
+            .map(|pid| pid as libc::pid_t)
+    }
+
+    /// Process group the terminal currently belongs to
+    pub fn foreground_process_group(&self) -> Option<libc::pid_t> {
+        self.pty_master
+            .as_ref()
+            .and_then(|master| master.lock().ok()?.process_group_leader())
+    }
+
+    /// Process group stopped by a suspend, waiting to be resumed
+    pub fn suspended_group(&self) -> Option<libc::pid_t> {
+        self.state().suspended
+    }
+
+    pub fn set_suspended(&self, group: Option<libc::pid_t>) {
+        self.state().suspended = group;
+    }
+
+    /// Buffer output and publish it to attached clients
+    pub fn add_output(&self, output: Vec<u8>) {
+        let mut state = self.state();
+        let now = Instant::now();
+        state.first_output_at.get_or_insert(now);
+        state.last_output_at = Some(now);
+        state.paste_mode.feed(&output);
+        state.screen.process(&output);
+        state.output_buffer.push(output.clone());
+        let offset = state.output_buffer.end_offset();
+        // Nobody attached is not an error, the output stays buffered
+        let _ = self.events.send(SessionEvent::Output {
+            offset,
+            data: output,
+        });
+    }
+
+    /// Follow a resize of the session's terminal
+    pub fn resize_screen(&self, size: TerminalSize) {
+        self.state().screen.resize(size);
+    }
+
+    /// Whether the child has turned on bracketed paste
+    pub fn bracketed_paste(&self) -> bool {
+        self.state().paste_mode.enabled()
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.state().exit_status.is_some()
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.state().exit_status.clone()
+    }
+
+    /// Record the exit status without announcing it
+    pub fn set_exit_status(&self, status: ExitStatus) {
+        self.state().exit_status = Some(status);
+    }
+
+    /// Whether `mark_exited` has told attached clients about the exit
+    pub fn exit_announced(&self) -> bool {
+        self.state().exit_announced
+    }
+
+    /// Record the exit status and tell attached clients the session closed
+    ///
+    /// Returns the reason given to them.
+    pub fn mark_exited(&self, status: ExitStatus) -> String {
+        let reason = format!("Process exited: {}", status);
+        let mut state = self.state();
+        state.exit_status = Some(status);
+        state.exit_announced = true;
+        let _ = self.events.send(SessionEvent::Closed {
+            reason: reason.clone(),
+        });
+        reason
+    }
+
+    /// Process groups that make up the session's process tree
+    ///
+    /// The child leads its own group as the PTY session leader; tools it runs
+    /// in the foreground may sit in a separate group on the same terminal.
+    pub fn process_groups(&self) -> Vec<libc::pid_t> {
+        let mut groups = Vec::new();
+
+        if let Some(pid) = self.child_pid() {
+            groups.push(pid);
+        }
+
+        if let Some(pgid) = self.foreground_process_group() {
+            if !groups.contains(&pgid) {
+                groups.push(pgid);
+            }
+        }
+
+        // SAFETY: getpgrp cannot fail
+        let own_group = unsafe { libc::getpgrp() };
+        groups.retain(|&pgid| pgid > 0 && pgid != own_group);
+        groups
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Snapshot the session's output and subscribe to everything after it
+    ///
+    /// With `after`, only output past that stream offset is returned,
+    /// provided the scrollback still holds all of it. Otherwise the client
+    /// gets the screen as it stands, with its scrollback, rather than a
+    /// replay of output that may have been redrawn many times over. Events
+    /// are published under the state lock, so taking the snapshot under it
+    /// here guarantees nothing is missed or repeated.
+    pub fn attach(&self, after: Option<u64>) -> Attachment {
+        let mut state = self.state();
+        let resumed = after.and_then(|offset| state.output_buffer.since(offset));
+        Attachment {
+            resumed: resumed.is_some(),
+            history: resumed.unwrap_or_else(|| state.screen.snapshot()),
+            offset: state.output_buffer.end_offset(),
+            ready: state.ready,
+            events: self.events.subscribe(),
+        }
+    }
+
+    pub fn get_all_output(&self) -> Vec<u8> {
+        self.state().output_buffer.contents()
     }
 }
 
@@ -90,6 +423,97 @@ pub struct SessionInfo {
     pub name: String,
     pub directory: String,
     pub created_at: String, // ISO 8601 timestamp
+    #[serde(default)]
+    pub status: SessionStatus,
+}
+
+/// Where a session is in its lifecycle, as far as the server can tell
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// Spawned, but the process has not printed anything yet
+    #[default]
+    Starting,
+
+    /// Producing output, or running a job in the foreground
+    Running,
+
+    /// Quiet for a long time
+    Idle,
+
+    /// Went quiet recently, most likely waiting on the user
+    WaitingForInput,
+
+    /// Suspended by a client until it is resumed
+    Paused,
+
+    /// The process exited; the session stays until it is closed
+    Exited,
+}
+
+impl SessionStatus {
+    /// Short human readable label
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Starting => "Starting",
+            Self::Running => "Running",
+            Self::Idle => "Idle",
+            Self::WaitingForInput => "Waiting for input",
+            Self::Paused => "Paused",
+            Self::Exited => "Exited",
+        }
+    }
+}
+
+/// Outcome of closing a session, reported by the REST API
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SessionShutdown {
+    /// Every process exited within the grace period, without SIGKILL
+    pub clean: bool,
+    /// Exit status of the session's main process, if it was collected
+    pub exit_status: Option<String>,
+}
+
+/// Job control a client can apply to a session's foreground job
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SessionSignal {
+    /// SIGINT, as Ctrl-C would send
+    Interrupt,
+
+    /// Stop the job until it is resumed
+    Suspend,
+
+    /// SIGCONT for a suspended job
+    Resume,
+
+    /// End of input, as Ctrl-D would type
+    Eof,
+}
+
+/// Terminal dimensions in character cells
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TerminalSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl TerminalSize {
+    /// Largest dimension accepted from clients
+    pub const MAX_DIMENSION: u16 = 1000;
+
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self { rows, cols }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1..=Self::MAX_DIMENSION).contains(&self.rows)
+            && (1..=Self::MAX_DIMENSION).contains(&self.cols)
+    }
+}
+
+impl Default for TerminalSize {
+    fn default() -> Self {
+        Self { rows: 24, cols: 80 }
+    }
 }
 
 /// Configuration for creating new sessions
@@ -97,6 +521,12 @@ pub struct SessionInfo {
 pub struct SessionConfig {
     pub name: String,
     pub directory: String,
+    /// Initial PTY size, defaulting to 24x80
+    #[serde(default)]
+    pub size: Option<TerminalSize>,
+    /// Task typed into the session by the server once it is ready
+    #[serde(default)]
+    pub initial_prompt: Option<String>,
 }
 
 impl SessionConfig {
@@ -119,6 +549,11 @@ impl SessionConfig {
         if !path.is_dir() {
             anyhow::bail!("Path is not a directory: {}", self.directory);
         }
+        if let Some(size) = self.size {
+            if !size.is_valid() {
+                anyhow::bail!("Invalid terminal size: {}x{}", size.rows, size.cols);
+            }
+        }
         Ok(())
     }
 }
@@ -139,8 +574,11 @@ mod tests {
         assert_eq!(session.id, "test-id");
         assert_eq!(session.name, "Test Session");
         assert_eq!(session.directory, "/tmp");
-        assert!(session.pty_pair.is_none());
-        assert!(session.output_buffer.is_empty());
+        assert!(session.pty_master.is_none());
+        assert!(session.child.is_none());
+        assert!(!session.has_exited());
+        assert!(session.pty.is_none());
+        assert!(session.get_all_output().is_empty());
     }
 
     #[test]
@@ -159,6 +597,162 @@ mod tests {
         assert!(chrono::DateTime::parse_from_rfc3339(&info.created_at).is_ok());
     }
 
+    #[test]
+    fn test_session_output_is_published() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        );
+        let mut events = session.subscribe();
+
+        session.add_output(b"hello\n".to_vec());
+
+        assert_eq!(session.get_all_output(), b"hello\n");
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SessionEvent::Output {
+                offset: 6,
+                data: b"hello\n".to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn test_session_attach_resumes_after_offset() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        )
+        .with_scrollback_limit(8);
+        session.add_output(b"abcd".to_vec());
+        session.add_output(b"efgh".to_vec());
+
+        let attachment = session.attach(Some(6));
+        assert!(attachment.resumed);
+        assert_eq!(attachment.history, b"gh");
+        assert_eq!(attachment.offset, 8);
+
+        // Once the requested output is evicted the client gets the screen
+        session.add_output(b"ijkl".to_vec());
+        let attachment = session.attach(Some(2));
+        assert!(!attachment.resumed);
+        assert_eq!(attachment.history, b"abcdefghijkl\x1b[13G");
+        assert_eq!(attachment.offset, 12);
+    }
+
+    #[test]
+    fn test_session_exit_is_published() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        );
+        let mut events = session.subscribe();
+
+        // Reaped, but not yet announced
+        session.set_exit_status(ExitStatus::with_exit_code(3));
+        assert!(session.has_exited());
+        assert!(!session.exit_announced());
+        assert!(events.try_recv().is_err());
+
+        session.mark_exited(ExitStatus::with_exit_code(3));
+
+        assert!(session.exit_announced());
+        assert_eq!(session.exit_status().unwrap().exit_code(), 3);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SessionEvent::Closed {
+                reason: "Process exited: Exited with code 3".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_session_status_follows_activity() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        );
+        let idle_after = Duration::from_secs(60);
+
+        assert_eq!(session.update_status(idle_after), None);
+        assert_eq!(session.status(), SessionStatus::Starting);
+
+        session.add_output(b"working\n".to_vec());
+        assert_eq!(
+            session.update_status(idle_after),
+            Some(SessionStatus::Running)
+        );
+        assert_eq!(session.update_status(idle_after), None);
+
+        session.state().last_output_at = Some(Instant::now() - Duration::from_secs(10));
+        assert_eq!(
+            session.update_status(idle_after),
+            Some(SessionStatus::WaitingForInput)
+        );
+
+        session.state().last_output_at = Some(Instant::now() - Duration::from_secs(120));
+        assert_eq!(session.update_status(idle_after), Some(SessionStatus::Idle));
+
+        session.mark_exited(ExitStatus::with_exit_code(0));
+        assert_eq!(
+            session.update_status(idle_after),
+            Some(SessionStatus::Exited)
+        );
+        assert_eq!(session.to_info().status, SessionStatus::Exited);
+    }
+
+    #[test]
+    fn test_session_ready_after_output_settles() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        );
+        let mut events = session.subscribe();
+
+        assert!(!session.update_ready());
+        session.add_output(b"> ".to_vec());
+        assert!(!session.update_ready());
+
+        session.state().last_output_at = Some(Instant::now() - READY_SETTLE);
+        assert!(session.update_ready());
+        assert!(!session.update_ready());
+        assert!(session.attach(None).ready);
+
+        assert_eq!(
+            events.try_recv().unwrap(),
+            SessionEvent::Output {
+                offset: 2,
+                data: b"> ".to_vec()
+            }
+        );
+        assert_eq!(events.try_recv().unwrap(), SessionEvent::Ready);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_ready_timeout_needs_output() {
+        let session = Session::new(
+            "test-id".to_string(),
+            "Test Session".to_string(),
+            "/tmp".to_string(),
+        );
+
+        // Silence means it is still starting, however long it lasts
+        assert!(!session.update_ready());
+
+        // Output that keeps coming is taken as ready once it has run long enough
+        session.add_output(b"loading |".to_vec());
+        assert!(!session.update_ready());
+        session.state().first_output_at = Some(Instant::now() - READY_TIMEOUT);
+        session.add_output(b"\x08/".to_vec());
+        assert!(session.update_ready());
+    }
+
     #[test]
     fn test_session_info_serialization() {
         let info = SessionInfo {
@@ -166,6 +760,7 @@ mod tests {
             name: "Test Session".to_string(),
             directory: "/tmp".to_string(),
             created_at: "2024-01-01T00:00:00Z".to_string(),
+            status: SessionStatus::WaitingForInput,
         };
 
         let json = serde_json::to_string(&info).unwrap();
@@ -179,6 +774,8 @@ mod tests {
         let config = SessionConfig {
             name: "Test Session".to_string(),
             directory: "/tmp".to_string(),
+            size: Some(TerminalSize::new(40, 120)),
+            initial_prompt: Some("Fix the tests".to_string()),
         };
 
         let json = serde_json::to_string(&config).unwrap();
@@ -186,4 +783,16 @@ mod tests {
 
         assert_eq!(config, deserialized);
     }
+
+    #[test]
+    fn test_session_config_rejects_invalid_size() {
+        let config = SessionConfig {
+            name: "Test Session".to_string(),
+            directory: "/tmp".to_string(),
+            size: Some(TerminalSize::new(0, 80)),
+            initial_prompt: None,
+        };
+
+        assert!(config.validate().is_err());
+    }
 }
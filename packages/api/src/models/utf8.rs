@@ -0,0 +1,111 @@
+/// Streaming UTF-8 decoder for terminal output
+///
+/// PTY reads split the byte stream at arbitrary points, often in the middle
+/// of a multi-byte character. The decoder holds on to an incomplete trailing
+/// sequence until the next chunk arrives instead of replacing it with U+FFFD.
+/// Genuinely invalid bytes are still replaced, as with `from_utf8_lossy`.
+#[derive(Debug, Clone, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode the next chunk, keeping any incomplete trailing character
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut output = String::with_capacity(input.len());
+        let mut rest = input.as_slice();
+
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    output.push_str(valid);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Safe: `valid_up_to` marks the end of well-formed input
+                    output.push_str(std::str::from_utf8(valid).unwrap_or_default());
+
+                    match e.error_len() {
+                        Some(invalid) => {
+                            output.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[invalid..];
+                        }
+                        None => {
+                            // Truncated sequence, wait for the rest of it
+                            self.pending = after.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Bytes held back waiting for the rest of a character
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    /// Take the held back bytes, leaving the decoder empty
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Flush the stream, replacing a truncated final character
+    pub fn finish(&mut self) -> String {
+        let pending = self.take_pending();
+        String::from_utf8_lossy(&pending).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_passes_ascii_through() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(decoder.decode(b"hello"), "hello");
+        assert!(decoder.pending().is_empty());
+    }
+
+    #[test]
+    fn test_decoder_joins_characters_split_across_chunks() {
+        let bytes = "a🦀b".as_bytes();
+        let mut decoder = Utf8Decoder::new();
+
+        // Split inside the four byte crab
+        assert_eq!(decoder.decode(&bytes[..3]), "a");
+        assert_eq!(decoder.pending(), &bytes[1..3]);
+        assert_eq!(decoder.decode(&bytes[3..4]), "");
+        assert_eq!(decoder.decode(&bytes[4..]), "🦀b");
+        assert!(decoder.pending().is_empty());
+    }
+
+    #[test]
+    fn test_decoder_replaces_invalid_bytes() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+    }
+
+    #[test]
+    fn test_decoder_finish_flushes_truncated_character() {
+        let mut decoder = Utf8Decoder::new();
+
+        assert_eq!(decoder.decode(&"é".as_bytes()[..1]), "");
+        assert_eq!(decoder.finish(), "\u{FFFD}");
+        assert!(decoder.pending().is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+//! Per-client queue of items waiting to be sent down a WebSocket
+
+use crate::models::message::{FrameKind, OutputEncoding, ServerEvent};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+
+/// Live output a client may have queued before it has to resync, in bytes
+pub const DEFAULT_CLIENT_QUEUE_BYTES: usize = 1024 * 1024;
+
+/// Items queued for delivery to a client
+///
+/// Output stays raw until it is sent, so it can be encoded the way the client
+/// negotiated at that moment.
+#[derive(Debug)]
+pub enum Outgoing {
+    Event(ServerEvent),
+    /// Switch encoding once everything queued before it has been sent
+    SetEncoding(OutputEncoding),
+    /// Drop characters held back for a session, its stream continues from
+    /// an offset the client chose
+    Restart {
+        session_id: String,
+    },
+    Output {
+        kind: FrameKind,
+        session_id: String,
+        /// Stream offset just past `data`
+        offset: u64,
+        data: Vec<u8>,
+    },
+}
+
+impl Outgoing {
+    /// Whether this is live output, as opposed to events and snapshots
+    pub fn is_live_output(&self) -> bool {
+        matches!(
+            self,
+            Outgoing::Output {
+                kind: FrameKind::Output,
+                ..
+            }
+        )
+    }
+
+    /// Bytes of live output this item holds
+    fn live_bytes(&self) -> usize {
+        match self {
+            Outgoing::Output {
+                kind: FrameKind::Output,
+                data,
+                ..
+            } => data.len(),
+            _ => 0,
+        }
+    }
+
+    /// Whether this is output for `session_id` that a resync makes obsolete
+    fn is_output_for(&self, session_id: &str) -> bool {
+        match self {
+            Outgoing::Output { session_id: id, .. } => id == session_id,
+            _ => false,
+        }
+    }
+
+    fn is_resync_for(&self, session_id: &str) -> bool {
+        match self {
+            Outgoing::Event(ServerEvent::ResyncNeeded { session_id: id }) => id == session_id,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    items: VecDeque<Outgoing>,
+    /// Live output bytes in `items`
+    live_bytes: usize,
+    /// Sessions whose output is dropped until the client fetches history
+    stalled: HashSet<String>,
+}
+
+/// Bounded queue between a client's session forwarders and its socket
+///
+/// Pushing never waits, so a slow browser cannot hold up a session. Live
+/// output joins the previous frame when it continues it. Once more than
+/// `limit` bytes of output are waiting, the session's queued output is
+/// replaced with a `ResyncNeeded` marker and further output is dropped until
+/// the client resubscribes and gets a history snapshot instead.
+#[derive(Debug, Clone)]
+pub struct OutgoingQueue {
+    state: Arc<Mutex<QueueState>>,
+    notify: Arc<Notify>,
+    limit: usize,
+}
+
+impl OutgoingQueue {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Arc::default(),
+            notify: Arc::new(Notify::new()),
+            limit,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        // Nothing panics while holding the lock, so poisoning cannot leave
+        // the queue in a broken state
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queue an item regardless of how much is waiting
+    ///
+    /// For events and snapshots the client asked for; live output goes
+    /// through `push_output`.
+    pub fn push(&self, item: Outgoing) {
+        let mut state = self.lock();
+        state.live_bytes += item.live_bytes();
+        state.items.push_back(item);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// Queue live output for a session, ending at stream offset `offset`
+    pub fn push_output(&self, session_id: &str, offset: u64, data: Vec<u8>) {
+        let mut state = self.lock();
+        if state.stalled.contains(session_id) {
+            return;
+        }
+        if state.live_bytes + data.len() > self.limit {
+            tracing::warn!(
+                "Client fell behind on session {} with {} bytes queued, asking it to resync",
+                session_id,
+                state.live_bytes
+            );
+            Self::stall(&mut state, session_id);
+            drop(state);
+            self.notify.notify_one();
+            return;
+        }
+
+        state.live_bytes += data.len();
+        let len = data.len() as u64;
+        if let Some(Outgoing::Output {
+            kind: FrameKind::Output,
+            session_id: last_id,
+            offset: last_offset,
+            data: last_data,
+        }) = state.items.back_mut()
+        {
+            if last_id == session_id && *last_offset + len == offset {
+                last_data.extend(data);
+                *last_offset = offset;
+                return;
+            }
+        }
+
+        state.items.push_back(Outgoing::Output {
+            kind: FrameKind::Output,
+            session_id: session_id.to_string(),
+            offset,
+            data,
+        });
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// Give up on delivering a session's output in order, asking the client
+    /// to fetch history instead
+    pub fn resync(&self, session_id: &str) {
+        let mut state = self.lock();
+        if state.stalled.contains(session_id) {
+            return;
+        }
+        Self::stall(&mut state, session_id);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    fn stall(state: &mut QueueState, session_id: &str) {
+        Self::remove_output(state, session_id);
+        state
+            .items
+            .push_back(Outgoing::Event(ServerEvent::ResyncNeeded {
+                session_id: session_id.to_string(),
+            }));
+        state.stalled.insert(session_id.to_string());
+    }
+
+    fn remove_output(state: &mut QueueState, session_id: &str) {
+        let QueueState {
+            items, live_bytes, ..
+        } = state;
+        items.retain(|item| {
+            let obsolete = item.is_output_for(session_id);
+            if obsolete {
+                *live_bytes -= item.live_bytes();
+            }
+            !obsolete
+        });
+    }
+
+    /// Let a session's output through again, once its forwarder is gone
+    ///
+    /// A resync marker the client has not seen yet is dropped, since it is
+    /// about to get a snapshot anyway.
+    pub fn resume(&self, session_id: &str) {
+        let mut state = self.lock();
+        if state.stalled.remove(session_id) {
+            state.items.retain(|item| !item.is_resync_for(session_id));
+        }
+    }
+
+    /// Drop everything still queued for a session the client has left
+    ///
+    /// Its output and any resync marker are removed and the session is no
+    /// longer stalled, so a later subscription starts afresh.
+    pub fn discard(&self, session_id: &str) {
+        let mut state = self.lock();
+        Self::remove_output(&mut state, session_id);
+        state.stalled.remove(session_id);
+        state.items.retain(|item| !item.is_resync_for(session_id));
+    }
+
+    /// Take the next item without waiting
+    pub fn try_pop(&self) -> Option<Outgoing> {
+        let mut state = self.lock();
+        let item = state.items.pop_front()?;
+        state.live_bytes -= item.live_bytes();
+        Some(item)
+    }
+
+    /// Wait for the next item
+    ///
+    /// Cancel safe: an item is only taken when this resolves.
+    pub async fn pop(&self) -> Outgoing {
+        loop {
+            // Registered before checking, so a push in between still wakes us
+            let notified = self.notify.notified();
+            if let Some(item) = self.try_pop() {
+                return item;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &OutgoingQueue) -> Vec<Outgoing> {
+        std::iter::from_fn(|| queue.try_pop()).collect()
+    }
+
+    #[test]
+    fn test_contiguous_output_is_coalesced() {
+        let queue = OutgoingQueue::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        queue.push_output("s", 3, b"abc".to_vec());
+        queue.push_output("s", 6, b"def".to_vec());
+        queue.push_output("other", 2, b"xy".to_vec());
+        queue.push_output("s", 8, b"gh".to_vec());
+
+        let items = drain(&queue);
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            Outgoing::Output { session_id, offset: 6, data, .. }
+                if session_id == "s" && data == b"abcdef"
+        ));
+        assert!(matches!(
+            &items[2],
+            Outgoing::Output { session_id, offset: 8, data, .. }
+                if session_id == "s" && data == b"gh"
+        ));
+    }
+
+    #[test]
+    fn test_overflow_replaces_output_with_resync_marker() {
+        let queue = OutgoingQueue::new(8);
+        queue.push_output("s", 4, b"abcd".to_vec());
+        queue.push(Outgoing::Event(ServerEvent::SessionReady {
+            session_id: "s".into(),
+        }));
+        queue.push_output("other", 2, b"xy".to_vec());
+        queue.push_output("s", 8, b"efgh".to_vec());
+        // Dropped while the client has yet to resync
+        queue.push_output("s", 9, b"i".to_vec());
+
+        let items = drain(&queue);
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            Outgoing::Event(ServerEvent::SessionReady { .. })
+        ));
+        assert!(items[1].is_output_for("other"));
+        assert!(items[2].is_resync_for("s"));
+
+        // Once the client resubscribed, output flows again
+        queue.resume("s");
+        queue.push_output("s", 12, b"jkl".to_vec());
+        let items = drain(&queue);
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_output_for("s"));
+    }
+
+    #[test]
+    fn test_resume_drops_unsent_resync_marker() {
+        let queue = OutgoingQueue::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        queue.resync("s");
+        queue.resync("s");
+        queue.resume("s");
+        assert!(drain(&queue).is_empty());
+    }
+
+    #[test]
+    fn test_discard_drops_queued_output_for_session() {
+        let queue = OutgoingQueue::new(8);
+        queue.push_output("s", 4, b"abcd".to_vec());
+        queue.push_output("other", 2, b"xy".to_vec());
+        queue.push_output("s", 9, b"efghi".to_vec());
+        queue.push_output("s", 12, b"jkl".to_vec());
+        queue.discard("s");
+
+        let items = drain(&queue);
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_output_for("other"));
+
+        // Neither stalled nor counting the dropped bytes against the limit
+        queue.push_output("s", 20, b"mnopqrst".to_vec());
+        let items = drain(&queue);
+        assert_eq!(items.len(), 1);
+        assert!(items[0].is_output_for("s"));
+    }
+
+    #[tokio::test]
+    async fn test_pop_waits_for_pushes() {
+        let queue = OutgoingQueue::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let producer = queue.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            producer.push_output("s", 1, b"a".to_vec());
+        });
+
+        let item = tokio::time::timeout(std::time::Duration::from_secs(5), queue.pop())
+            .await
+            .expect("timed out waiting for output");
+        assert!(item.is_output_for("s"));
+    }
+}
@@ -1,6 +1,12 @@
+use crate::models::session::TerminalSize;
 use anyhow::{Context, Result};
-use portable_pty::{CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
-use std::io::{Read, Write};
+use portable_pty::{Child, CommandBuilder, MasterPty, NativePtySystem, PtySize, PtySystem};
+
+/// A freshly spawned Claude process and the master side of its PTY
+pub struct ClaudeProcess {
+    pub master: Box<dyn MasterPty + Send>,
+    pub child: Box<dyn Child + Send + Sync>,
+}
 
 pub struct ProcessManager {
     pty_system: NativePtySystem,
@@ -22,59 +28,106 @@ impl ProcessManager {
     }
 
     /// Spawn Claude CLI process with PTY
-    pub fn spawn_claude(&self, directory: &str, claude_path: &str) -> Result<PtyPair> {
-        let pty_size = PtySize {
-            rows: 24,
-            cols: 80,
-            pixel_width: 0,
-            pixel_height: 0,
-        };
-
+    ///
+    /// Only the master side is returned: the slave is closed here so that
+    /// reads from the master fail once the child exits.
+    pub fn spawn_claude(
+        &self,
+        directory: &str,
+        claude_path: &str,
+        size: TerminalSize,
+    ) -> Result<ClaudeProcess> {
         let pty_pair = self
             .pty_system
-            .openpty(pty_size)
+            .openpty(Self::pty_size(size))
             .context("Failed to create PTY")?;
 
         let mut cmd = CommandBuilder::new(claude_path);
         cmd.cwd(directory);
 
-        let _child = pty_pair
+        let child = pty_pair
             .slave
             .spawn_command(cmd)
             .context("Failed to spawn Claude process")?;
 
-        Ok(pty_pair)
+        Ok(ClaudeProcess {
+            master: pty_pair.master,
+            child,
+        })
     }
 
-    /// Read available output from PTY (non-blocking)
-    pub fn read_output(reader: &mut Box<dyn Read + Send>) -> Result<String> {
-        let mut buffer = [0u8; 4096];
-        let mut output = String::new();
-
-        loop {
-            match reader.read(&mut buffer) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let chunk = String::from_utf8_lossy(&buffer[..n]);
-                    output.push_str(&chunk);
-                    if n < buffer.len() {
-                        break;
-                    }
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                Err(e) => return Err(anyhow::anyhow!("Read error: {}", e)),
-            }
-        }
+    /// Resize the PTY, which delivers SIGWINCH to its foreground processes
+    pub fn resize(master: &dyn MasterPty, size: TerminalSize) -> Result<()> {
+        master
+            .resize(Self::pty_size(size))
+            .context("Failed to resize PTY")
+    }
 
-        Ok(output)
+    fn pty_size(size: TerminalSize) -> PtySize {
+        PtySize {
+            rows: size.rows,
+            cols: size.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
     }
 
-    /// Write input to PTY
-    pub fn write_input(writer: &mut Box<dyn Write + Send>, input: &str) -> Result<()> {
-        writer.write_all(input.as_bytes())?;
-        writer.flush()?;
+    /// Send a signal to every process in a process group
+    ///
+    /// A group with no processes left is not an error.
+    pub fn signal_process_group(pgid: libc::pid_t, signal: libc::c_int) -> Result<()> {
+        // Group 0 and negative ids address the server's own processes
+        if pgid <= 0 {
+            anyhow::bail!("Invalid process group: {}", pgid);
+        }
+
+        // SAFETY: killpg has no memory-safety preconditions
+        if unsafe { libc::killpg(pgid, signal) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                return Err(err)
+                    .with_context(|| format!("Failed to signal process group {}", pgid));
+            }
+        }
         Ok(())
     }
+
+    /// Whether any live process is left in a process group
+    pub fn process_group_alive(pgid: libc::pid_t) -> bool {
+        if pgid <= 0 {
+            return false;
+        }
+
+        // killpg also counts zombies, which never go away when they are
+        // reparented to an init that does not reap (e.g. in containers)
+        #[cfg(target_os = "linux")]
+        if let Ok(entries) = std::fs::read_dir("/proc") {
+            return entries.flatten().any(|entry| {
+                std::fs::read_to_string(entry.path().join("stat"))
+                    .ok()
+                    .and_then(|stat| parse_proc_stat(&stat))
+                    .is_some_and(|(state, group)| group == pgid && state != 'Z' && state != 'X')
+            });
+        }
+
+        // SAFETY: signal 0 only checks for existence and permissions
+        if unsafe { libc::killpg(pgid, 0) } == 0 {
+            return true;
+        }
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+/// Extract the state and process group from a `/proc/<pid>/stat` line
+#[cfg(target_os = "linux")]
+fn parse_proc_stat(stat: &str) -> Option<(char, libc::pid_t)> {
+    // The command name is parenthesised and may itself contain spaces or ')'
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let mut fields = rest.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let _ppid = fields.next()?;
+    let pgrp = fields.next()?.parse().ok()?;
+    Some((state, pgrp))
 }
 
 impl Default for ProcessManager {
@@ -82,3 +135,30 @@ impl Default for ProcessManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_proc_stat() {
+        let stat = "1234 (my (odd) cmd) S 1 1230 1230 0 -1 4194560 120 0 0 0";
+        assert_eq!(parse_proc_stat(stat), Some(('S', 1230)));
+        assert_eq!(parse_proc_stat("garbage"), None);
+    }
+
+    #[test]
+    fn test_own_process_group_is_alive() {
+        // SAFETY: getpgrp cannot fail
+        let own_group = unsafe { libc::getpgrp() };
+        assert!(ProcessManager::process_group_alive(own_group));
+        assert!(!ProcessManager::process_group_alive(0));
+    }
+
+    #[test]
+    fn test_signal_rejects_invalid_group() {
+        assert!(ProcessManager::signal_process_group(0, libc::SIGTERM).is_err());
+        assert!(ProcessManager::signal_process_group(-1, libc::SIGTERM).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+use std::sync::Arc;
+use tokio::io::unix::AsyncFd;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+/// Non-blocking handle on a PTY master, driven by the tokio reactor
+///
+/// Reads and writes wait for readiness instead of parking a thread, so an
+/// idle session costs one registered file descriptor rather than a blocked
+/// worker, and hundreds of sessions can share the runtime with the
+/// WebSocket and REST handlers.
+#[derive(Debug)]
+pub struct AsyncPty {
+    file: AsyncFd<File>,
+    /// Keeps concurrent inputs from interleaving on partial writes
+    write_lock: Mutex<()>,
+}
+
+impl AsyncPty {
+    /// Register a duplicate of the master's descriptor with the runtime
+    ///
+    /// The descriptor is switched to non-blocking mode, which also applies
+    /// to the original since both share one open file description.
+    pub fn new(master_fd: RawFd) -> Result<Self> {
+        // SAFETY: the caller's master owns the descriptor and keeps it open
+        // for the duration of this call; we only duplicate it
+        let fd = unsafe { BorrowedFd::borrow_raw(master_fd) }
+            .try_clone_to_owned()
+            .context("Failed to duplicate PTY master")?;
+        set_nonblocking(fd.as_raw_fd()).context("Failed to make PTY master non-blocking")?;
+
+        let file = AsyncFd::new(File::from(fd)).context("Failed to register PTY master")?;
+        Ok(Self {
+            file,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    /// Wait for output and read as much as is available into `buf`
+    ///
+    /// `Ok(0)` means end of file. Once every process has closed the slave,
+    /// Linux reports EIO instead.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.file.readable().await?;
+            match guard.try_io(|file| (&mut file.get_ref()).read(buf)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Write all of `data`, waiting whenever the terminal's buffer is full
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        let _write = self.write_lock.lock().await;
+
+        while !data.is_empty() {
+            let mut guard = self.file.writable().await?;
+            match guard.try_io(|file| (&mut file.get_ref()).write(data)) {
+                Ok(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(Ok(written)) => data = &data[written..],
+                Ok(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Ok(Err(e)) => return Err(e),
+                Err(_would_block) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Queue of input for a PTY, written out by a task of its own
+///
+/// Queueing never waits for the terminal, so a child that stops reading
+/// cannot hold up whoever types into it; once the queue is full, further
+/// input is refused. The task stops when the writer is dropped.
+#[derive(Debug)]
+pub struct PtyWriter {
+    queue: mpsc::Sender<Vec<u8>>,
+    task: JoinHandle<()>,
+}
+
+impl PtyWriter {
+    /// Start writing to `pty`, holding at most `capacity` inputs back
+    pub fn spawn(pty: Arc<AsyncPty>, capacity: usize) -> Self {
+        let (queue, mut inputs) = mpsc::channel::<Vec<u8>>(capacity);
+        let task = tokio::spawn(async move {
+            while let Some(input) = inputs.recv().await {
+                if let Err(e) = pty.write_all(&input).await {
+                    tracing::debug!("PTY write ended: {}", e);
+                    break;
+                }
+            }
+        });
+        Self { queue, task }
+    }
+
+    /// Queue `data` to be written after everything queued before it
+    pub fn write(&self, data: Vec<u8>) -> io::Result<()> {
+        self.queue.try_send(data).map_err(|e| match e {
+            TrySendError::Full(_) => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "terminal is not reading its input",
+            ),
+            TrySendError::Closed(_) => io::ErrorKind::BrokenPipe.into(),
+        })
+    }
+}
+
+impl Drop for PtyWriter {
+    fn drop(&mut self) {
+        // A write stuck on a terminal nobody reads would otherwise keep the
+        // task, and the master with it, around for good
+        self.task.abort();
+    }
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    // SAFETY: fcntl on a descriptor we own has no memory-safety preconditions
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: as above
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use portable_pty::{NativePtySystem, PtySize, PtySystem};
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_async_pty_round_trip() {
+        let pair = NativePtySystem::default()
+            .openpty(PtySize::default())
+            .unwrap();
+        let pty = AsyncPty::new(pair.master.as_raw_fd().unwrap()).unwrap();
+
+        // The slave's line discipline echoes what the master writes
+        pty.write_all(b"hello\n").await.unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 64];
+        while !received.windows(5).any(|window| window == b"hello") {
+            let n = tokio::time::timeout(Duration::from_secs(5), pty.read(&mut buf))
+                .await
+                .expect("timed out waiting for the echo")
+                .unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_pty_writer_does_not_wait_for_the_terminal() {
+        let pair = NativePtySystem::default()
+            .openpty(PtySize::default())
+            .unwrap();
+        let pty = AsyncPty::new(pair.master.as_raw_fd().unwrap()).unwrap();
+        let writer = PtyWriter::spawn(Arc::new(pty), 4);
+
+        // Nothing reads the slave, so the terminal's buffer fills up and
+        // the queue behind it with it
+        let paste = vec![b'x'; 64 * 1024];
+        let mut refused = false;
+        for _ in 0..16 {
+            refused |= writer.write(paste.clone()).is_err();
+            tokio::task::yield_now().await;
+        }
+        assert!(refused);
+    }
+}
@@ -1,19 +1,54 @@
 use crate::config::Config;
-use crate::models::session::{Session, SessionConfig, SessionInfo, SessionMetadata};
+use crate::models::bracketed_paste::frame_paste;
+use crate::models::session::{
+    Attachment, Session, SessionChange, SessionConfig, SessionEvent, SessionInfo, SessionShutdown,
+    SessionSignal, TerminalSize,
+};
 use crate::process_manager::ProcessManager;
-use crate::state::app_state::SessionMetadataStore;
+use crate::pty_io::{AsyncPty, PtyWriter};
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+/// Largest chunk of output read from a PTY at once
+const OUTPUT_READ_SIZE: usize = 4096;
+
+/// Inputs a session holds back while its terminal is not reading, before
+/// refusing more
+const INPUT_QUEUE_CAPACITY: usize = 64;
+
+/// How often session processes are checked for exit and status changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long to let trailing output drain before announcing an exit
+const EXIT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a closing session is checked for remaining processes
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to wait for processes to disappear after SIGKILL
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Number of registry changes a slow watcher may fall behind by
+const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+/// The registry of running sessions
+///
+/// Clones share the same sessions, so this is the one source of truth for
+/// REST, WebSocket and any other transport. Changes to the set of sessions
+/// are published through [`SessionManager::watch`].
 #[derive(Debug)]
 pub struct SessionManager {
-    sessions: Arc<RwLock<HashMap<String, Session>>>,
+    /// Locked only to insert, remove or look up sessions; each session
+    /// guards its own mutable state
+    sessions: Arc<RwLock<HashMap<String, Arc<Session>>>>,
     process_manager: ProcessManager,
     config: Arc<Config>,
-    metadata_store: Option<SessionMetadataStore>,
+    changes: broadcast::Sender<SessionChange>,
 }
 
 impl Clone for SessionManager {
@@ -22,23 +57,31 @@ impl Clone for SessionManager {
             sessions: Arc::clone(&self.sessions),
             process_manager: ProcessManager::new(),
             config: Arc::clone(&self.config),
-            metadata_store: self.metadata_store.clone(),
+            changes: self.changes.clone(),
         }
     }
 }
 
 impl SessionManager {
     pub fn new(config: Arc<Config>) -> Self {
+        let (changes, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+
         Self {
             sessions: Arc::new(RwLock::new(HashMap::new())),
             process_manager: ProcessManager::new(),
             config,
-            metadata_store: None,
+            changes,
         }
     }
 
-    pub fn set_metadata_store(&mut self, store: SessionMetadataStore) {
-        self.metadata_store = Some(store);
+    /// Subscribe to sessions being created, exiting and closing
+    pub fn watch(&self) -> broadcast::Receiver<SessionChange> {
+        self.changes.subscribe()
+    }
+
+    fn publish(&self, change: SessionChange) {
+        // Nobody watching is not an error
+        let _ = self.changes.send(change);
     }
 
     pub async fn create_session(&self, session_config: SessionConfig) -> Result<String> {
@@ -56,26 +99,55 @@ impl SessionManager {
         drop(sessions);
 
         let session_id = Uuid::new_v4().to_string();
+        let size = session_config.size.unwrap_or_default();
         let mut session = Session::new(
             session_id.clone(),
             session_config.name,
             session_config.directory.clone(),
-        );
+        )
+        .with_scrollback_limit(self.config.scrollback_bytes)
+        .with_screen(size, self.config.screen_scrollback_lines);
+
+        let process = self.process_manager.spawn_claude(
+            &session_config.directory,
+            &self.config.claude_path,
+            size,
+        )?;
+        let pty_master = process.master;
+        let mut child = process.child;
 
-        let pty_pair = self
-            .process_manager
-            .spawn_claude(&session_config.directory, &self.config.claude_path)?;
+        let pty = match pty_master
+            .as_raw_fd()
+            .context("PTY master has no descriptor")
+            .and_then(AsyncPty::new)
+        {
+            Ok(pty) => Arc::new(pty),
+            Err(e) => {
+                // Nothing would ever reap or close the child otherwise
+                if let Err(kill_error) = child.kill() {
+                    tracing::warn!("Failed to kill session child: {}", kill_error);
+                }
+                let _ = child.wait();
+                return Err(e);
+            }
+        };
 
-        session.pty_pair = Some(Mutex::new(pty_pair));
+        session.pty = Some(Arc::clone(&pty));
+        session.input = Some(PtyWriter::spawn(Arc::clone(&pty), INPUT_QUEUE_CAPACITY));
+        session.pty_master = Some(Mutex::new(pty_master));
+        session.child = Some(Mutex::new(child));
 
+        let session = Arc::new(session);
         let mut sessions = self.sessions.write().await;
-        let metadata = SessionMetadata::from(&session);
-        sessions.insert(session_id.clone(), session);
+        sessions.insert(session_id.clone(), Arc::clone(&session));
+        drop(sessions);
 
-        // Update metadata store for WebSocket access
-        if let Some(ref store) = self.metadata_store {
-            store.update_metadata(&session_id, metadata).await;
-        }
+        let initial_prompt = session_config
+            .initial_prompt
+            .filter(|prompt| !prompt.trim().is_empty());
+        let output_pump = Self::spawn_output_pump(Arc::clone(&session), pty);
+        self.spawn_session_watcher(session_id.clone(), output_pump, initial_prompt);
+        self.publish(SessionChange::Created(session.to_info()));
 
         Ok(session_id)
     }
@@ -85,64 +157,768 @@ impl SessionManager {
         Ok(sessions.values().map(|s| s.to_info()).collect())
     }
 
-    pub async fn close_session(&self, session_id: &str) -> Result<()> {
+    /// Close a session, terminating its whole process tree
+    ///
+    /// The process groups get SIGHUP and SIGTERM, then SIGKILL if anything
+    /// is still running after the configured grace period.
+    pub async fn close_session(&self, session_id: &str) -> Result<SessionShutdown> {
         let mut sessions = self.sessions.write().await;
-        sessions.remove(session_id).context("Session not found")?;
+        let session = sessions.remove(session_id).context("Session not found")?;
+        drop(sessions);
 
-        // Remove from metadata store
-        if let Some(ref store) = self.metadata_store {
-            store.remove_metadata(session_id).await;
+        let already_announced = session.exit_announced();
+        let shutdown = Self::terminate_process_tree(
+            &session,
+            Duration::from_millis(self.config.shutdown_grace_ms),
+        )
+        .await;
+
+        tracing::info!(
+            "Session {} closed (clean: {}, exit status: {:?})",
+            session_id,
+            shutdown.clean,
+            shutdown.exit_status
+        );
+        if !already_announced {
+            let _ = session.events.send(SessionEvent::Closed {
+                reason: "Session closed".to_string(),
+            });
         }
+        self.publish(SessionChange::Closed {
+            session_id: session_id.to_string(),
+        });
 
-        Ok(())
+        Ok(shutdown)
     }
 
-    pub async fn session_exists(&self, session_id: &str) -> bool {
-        let sessions = self.sessions.read().await;
-        sessions.contains_key(session_id)
+    /// Process groups of the session that may still be signalled
+    ///
+    /// None once the child has been reaped: its pid, and with it the group
+    /// id, may then be handed out to an unrelated process.
+    fn signalable_process_groups(session: &Session) -> Vec<libc::pid_t> {
+        if session.has_exited() {
+            return Vec::new();
+        }
+        session.process_groups()
     }
 
-    pub async fn get_session_metadata(&self, session_id: &str) -> Option<SessionMetadata> {
-        let sessions = self.sessions.read().await;
-        sessions.get(session_id).map(SessionMetadata::from)
+    async fn terminate_process_tree(session: &Session, grace: Duration) -> SessionShutdown {
+        let groups = Self::signalable_process_groups(session);
+
+        for signal in [libc::SIGHUP, libc::SIGTERM] {
+            for &pgid in &groups {
+                if let Err(e) = ProcessManager::signal_process_group(pgid, signal) {
+                    tracing::warn!("{:#}", e);
+                }
+            }
+        }
+
+        let clean = Self::wait_for_process_groups(session, &groups, grace).await;
+        if !clean {
+            tracing::warn!(
+                "Session {} still running after {:?}, sending SIGKILL",
+                session.id,
+                grace
+            );
+            // The child may have been reaped during the grace period
+            let groups = Self::signalable_process_groups(session);
+            for &pgid in &groups {
+                if let Err(e) = ProcessManager::signal_process_group(pgid, libc::SIGKILL) {
+                    tracing::warn!("{:#}", e);
+                }
+            }
+            if !Self::wait_for_process_groups(session, &groups, KILL_TIMEOUT).await {
+                tracing::error!("Session {} processes survived SIGKILL", session.id);
+            }
+        }
+
+        SessionShutdown {
+            clean,
+            exit_status: session.exit_status().map(|status| status.to_string()),
+        }
     }
 
-    pub async fn add_session_output(&self, session_id: &str, output: String) -> Result<()> {
-        let mut sessions = self.sessions.write().await;
-        let session = sessions.get_mut(session_id).context("Session not found")?;
-        session.add_output(output);
-        Ok(())
+    /// Reap the child and wait until the given process groups are empty
+    async fn wait_for_process_groups(
+        session: &Session,
+        groups: &[libc::pid_t],
+        timeout: Duration,
+    ) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // The child stays a zombie member of its group until reaped
+            if !session.has_exited() {
+                let status = session
+                    .child
+                    .as_ref()
+                    .and_then(|child| child.lock().ok()?.try_wait().ok().flatten());
+                if let Some(status) = status {
+                    session.set_exit_status(status);
+                }
+            }
+
+            if !groups
+                .iter()
+                .any(|&pgid| ProcessManager::process_group_alive(pgid))
+            {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
     }
 
-    pub async fn get_session_output(&self, session_id: &str) -> Result<Vec<String>> {
+    /// Look up a session; the map is only locked for the lookup itself
+    async fn get(&self, session_id: &str) -> Result<Arc<Session>> {
         let sessions = self.sessions.read().await;
-        let session = sessions.get(session_id).context("Session not found")?;
-        Ok(session.get_all_output())
+        sessions
+            .get(session_id)
+            .cloned()
+            .context("Session not found")
     }
 
-    pub async fn get_pty_reader(&self, session_id: &str) -> Result<Box<dyn std::io::Read + Send>> {
+    pub async fn session_exists(&self, session_id: &str) -> bool {
         let sessions = self.sessions.read().await;
-        let session = sessions.get(session_id).context("Session not found")?;
-        let pty_pair = session.pty_pair.as_ref().context("No PTY")?;
-        let pty_pair = pty_pair
+        sessions.contains_key(session_id)
+    }
+
+    /// Scrollback snapshot, or what followed stream offset `after`, plus a
+    /// subscription to all output after it
+    pub async fn attach(&self, session_id: &str, after: Option<u64>) -> Result<Attachment> {
+        let session = self.get(session_id).await?;
+        Ok(session.attach(after))
+    }
+
+    /// Subscribe to output and lifecycle events of a session
+    pub async fn subscribe(&self, session_id: &str) -> Result<broadcast::Receiver<SessionEvent>> {
+        let session = self.get(session_id).await?;
+        Ok(session.subscribe())
+    }
+
+    /// Resize the session's PTY to match the client's character grid
+    pub async fn resize(&self, session_id: &str, size: TerminalSize) -> Result<()> {
+        if !size.is_valid() {
+            anyhow::bail!("Invalid terminal size: {}x{}", size.rows, size.cols);
+        }
+
+        let session = self.get(session_id).await?;
+        let pty_master = session.pty_master.as_ref().context("No PTY")?;
+        let pty_master = pty_master
             .lock()
             .map_err(|_| anyhow::anyhow!("Mutex poisoned"))?;
-        pty_pair
-            .master
-            .try_clone_reader()
-            .context("Failed to clone reader")
+        ProcessManager::resize(pty_master.as_ref(), size)?;
+        session.resize_screen(size);
+        Ok(())
     }
 
-    pub async fn get_pty_writer(&self, session_id: &str) -> Result<Box<dyn std::io::Write + Send>> {
-        let sessions = self.sessions.read().await;
-        let session = sessions.get(session_id).context("Session not found")?;
-        let pty_pair = session.pty_pair.as_ref().context("No PTY")?;
-        let pty_pair = pty_pair
+    /// Apply job control to the job holding the session's terminal
+    ///
+    /// `Eof` is typed as Ctrl-D rather than signalled. A suspend sends
+    /// SIGTSTP, except to the session's main process: its group has no
+    /// parent in the session, which makes it orphaned, and the kernel
+    /// discards SIGTSTP for orphaned groups, so it gets SIGSTOP instead.
+    pub async fn signal_session(&self, session_id: &str, signal: SessionSignal) -> Result<()> {
+        let session = self.get(session_id).await?;
+        if let Some(status) = session.exit_status() {
+            anyhow::bail!("Session process has exited ({})", status);
+        }
+        let child_pid = session.child_pid();
+        let foreground = || {
+            session
+                .foreground_process_group()
+                .or(child_pid)
+                .context("Session has no process group")
+        };
+
+        match signal {
+            SessionSignal::Interrupt => {
+                ProcessManager::signal_process_group(foreground()?, libc::SIGINT)?;
+            }
+            SessionSignal::Suspend => {
+                let group = foreground()?;
+                let stop = if Some(group) == child_pid {
+                    libc::SIGSTOP
+                } else {
+                    libc::SIGTSTP
+                };
+                ProcessManager::signal_process_group(group, stop)?;
+                session.set_suspended(Some(group));
+            }
+            SessionSignal::Resume => {
+                // A shell takes the terminal back from a job it saw stop
+                let group = match session.suspended_group() {
+                    Some(group) => group,
+                    None => foreground()?,
+                };
+                ProcessManager::signal_process_group(group, libc::SIGCONT)?;
+                session.set_suspended(None);
+            }
+            SessionSignal::Eof => {
+                let input = session.input.as_ref().context("No PTY")?;
+                input
+                    .write(b"\x04".to_vec())
+                    .context("Failed to write to PTY")?;
+            }
+        }
+
+        tracing::info!("Sent {:?} to session {}", signal, session_id);
+        self.refresh_status(&session);
+        Ok(())
+    }
+
+    /// Type text into the session as one paste
+    ///
+    /// Once the child has turned on bracketed paste the text is framed, so
+    /// its newlines do not submit it line by line. Nothing is submitted;
+    /// that takes a separate Enter.
+    pub async fn paste_input(&self, session_id: &str, text: &str) -> Result<()> {
+        let session = self.get(session_id).await?;
+        let input = if session.bracketed_paste() {
+            frame_paste(text)
+        } else {
+            text.to_string()
+        };
+        self.send_input(session_id, input).await
+    }
+
+    /// Queue input for the session's PTY
+    ///
+    /// Returns once the input is queued, not written, and fails instead of
+    /// waiting when the child has stopped reading and the queue is full.
+    pub async fn send_input(&self, session_id: &str, input: String) -> Result<()> {
+        let session = self.get(session_id).await?;
+        if let Some(status) = session.exit_status() {
+            anyhow::bail!("Session process has exited ({})", status);
+        }
+        let queue = session.input.as_ref().context("No PTY")?;
+        queue
+            .write(input.into_bytes())
+            .context("Failed to write to PTY")
+    }
+
+    /// Stream PTY output into the session until the child closes its side
+    ///
+    /// The master is polled by the runtime, so an idle session parks no
+    /// thread while it waits for output. The pump holds the session itself,
+    /// so output never touches the sessions map.
+    fn spawn_output_pump(session: Arc<Session>, pty: Arc<AsyncPty>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut buffer = vec![0u8; OUTPUT_READ_SIZE];
+            loop {
+                match pty.read(&mut buffer).await {
+                    Ok(0) => break,
+                    Ok(n) => session.add_output(buffer[..n].to_vec()),
+                    Err(e) => {
+                        // EIO is how the master reports that the child side closed
+                        tracing::debug!("PTY read ended for session {}: {}", session.id, e);
+                        break;
+                    }
+                }
+            }
+            tracing::info!("Output stream ended for session {}", session.id);
+        })
+    }
+
+    /// Keep the session's status current, type the initial prompt once the
+    /// session is ready and announce its exit once output drained
+    fn spawn_session_watcher(
+        &self,
+        session_id: String,
+        output_pump: JoinHandle<()>,
+        mut initial_prompt: Option<String>,
+    ) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            let status = loop {
+                interval.tick().await;
+                let Ok(session) = manager.get(&session_id).await else {
+                    tracing::debug!("Stopped watching closed session {}", session_id);
+                    return;
+                };
+                manager.refresh_status(&session);
+                if session.update_ready() {
+                    tracing::info!("Session {} is ready for input", session_id);
+                    if let Some(prompt) = initial_prompt.take() {
+                        manager.deliver_prompt(&session_id, prompt);
+                    }
+                }
+
+                match Self::try_wait(&session) {
+                    Ok(Some(status)) => {
+                        // Recorded right away, so nothing signals the reaped
+                        // pid while output drains
+                        session.set_exit_status(status.clone());
+                        break status;
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::debug!("Stopped watching session {}: {:#}", session_id, e);
+                        return;
+                    }
+                }
+            };
+
+            // Processes left behind by the child can keep the PTY open, so
+            // only wait a bounded time for the reader to reach EOF
+            if tokio::time::timeout(EXIT_DRAIN_TIMEOUT, output_pump)
+                .await
+                .is_err()
+            {
+                tracing::debug!("Output still open after session {} exited", session_id);
+            }
+
+            // A session closed meanwhile has already announced itself
+            if let Ok(session) = manager.get(&session_id).await {
+                tracing::info!("Session {} process exited: {}", session_id, status);
+                let reason = session.mark_exited(status);
+                manager.publish(SessionChange::Exited {
+                    session_id: session_id.clone(),
+                    reason,
+                });
+                manager.refresh_status(&session);
+            }
+        });
+    }
+
+    /// Type a prompt into the session without holding up the watcher
+    fn deliver_prompt(&self, session_id: &str, prompt: String) {
+        let manager = self.clone();
+        let session_id = session_id.to_string();
+        tokio::spawn(async move {
+            tracing::info!("Sending initial prompt to session {}", session_id);
+            let delivered = async {
+                manager.paste_input(&session_id, &prompt).await?;
+                manager.send_input(&session_id, "\r".to_string()).await
+            };
+            if let Err(e) = delivered.await {
+                tracing::warn!(
+                    "Failed to deliver initial prompt to {}: {:#}",
+                    session_id,
+                    e
+                );
+            }
+        });
+    }
+
+    /// Recompute a session's status and publish it if it changed
+    fn refresh_status(&self, session: &Session) {
+        let idle_after = Duration::from_secs(self.config.idle_after_secs);
+        if let Some(status) = session.update_status(idle_after) {
+            tracing::debug!("Session {} is now {:?}", session.id, status);
+            self.publish(SessionChange::StatusChanged {
+                session_id: session.id.clone(),
+                status,
+            });
+        }
+    }
+
+    /// Non-blocking check of the session child's exit status
+    fn try_wait(session: &Session) -> Result<Option<portable_pty::ExitStatus>> {
+        let child = session.child.as_ref().context("No child process")?;
+        let mut child = child
             .lock()
             .map_err(|_| anyhow::anyhow!("Mutex poisoned"))?;
-        pty_pair
-            .master
-            .take_writer()
-            .context("Failed to get writer")
+        child.try_wait().context("Failed to poll child process")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::session::SessionStatus;
+    use std::time::Duration;
+
+    fn test_manager() -> SessionManager {
+        // `cat` stands in for Claude: it echoes whatever reaches the PTY
+        manager_running("cat")
+    }
+
+    fn manager_running(command: &str) -> SessionManager {
+        let config = Config {
+            claude_path: command.to_string(),
+            shutdown_grace_ms: 300,
+            ..Config::default()
+        };
+        SessionManager::new(Arc::new(config))
+    }
+
+    /// Write an executable shell script to stand in for Claude
+    fn script(name: &str, body: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("thrallui-{}-{}", name, Uuid::new_v4()));
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    async fn wait_for_output(
+        events: &mut broadcast::Receiver<SessionEvent>,
+        expected: &str,
+    ) -> String {
+        let mut received = String::new();
+        while !received.contains(expected) {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for PTY output")
+                .unwrap();
+            if let SessionEvent::Output { data, .. } = event {
+                received.push_str(&String::from_utf8_lossy(&data));
+            }
+        }
+        received
+    }
+
+    fn test_session_config() -> SessionConfig {
+        SessionConfig {
+            name: "Test Session".to_string(),
+            directory: "/tmp".to_string(),
+            size: None,
+            initial_prompt: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_input_is_streamed_back_as_output() {
+        let manager = test_manager();
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        manager
+            .send_input(&session_id, "hello\n".to_string())
+            .await
+            .unwrap();
+
+        wait_for_output(&mut events, "hello").await;
+
+        let buffered = manager.get(&session_id).await.unwrap().get_all_output();
+        assert!(String::from_utf8_lossy(&buffered).contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_process_exit_closes_session() {
+        let manager = manager_running("true");
+        let mut changes = manager.watch();
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        let reason = loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for the session to close")
+                .unwrap();
+            if let SessionEvent::Closed { reason } = event {
+                break reason;
+            }
+        };
+
+        assert_eq!(reason, "Process exited: Success");
+        let result = manager.send_input(&session_id, "hello\n".to_string()).await;
+        assert!(result.is_err());
+
+        assert!(matches!(
+            changes.recv().await.unwrap(),
+            SessionChange::Created(_)
+        ));
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            SessionChange::Exited { session_id, reason }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_ready_follows_first_prompt() {
+        let command = script(
+            "prompt",
+            "sleep 0.2\nprintf 'prompt> '\nread line\necho got $line",
+        );
+        let manager = manager_running(&command);
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        let mut before_ready = String::new();
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for the session to become ready")
+                .unwrap();
+            match event {
+                SessionEvent::Output { data, .. } => {
+                    before_ready.push_str(&String::from_utf8_lossy(&data))
+                }
+                SessionEvent::Ready => break,
+                SessionEvent::Closed { reason } => panic!("session closed early: {}", reason),
+            }
+        }
+        assert!(before_ready.contains("prompt> "));
+
+        manager
+            .send_input(&session_id, "task\n".to_string())
+            .await
+            .unwrap();
+        wait_for_output(&mut events, "got task").await;
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_initial_prompt_is_delivered_once_ready() {
+        let command = script(
+            "initial",
+            "sleep 0.2\nprintf 'prompt> '\nread line\necho got $line",
+        );
+        let manager = manager_running(&command);
+        let config = SessionConfig {
+            initial_prompt: Some("task".to_string()),
+            ..test_session_config()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        // Nobody attached sends anything, the server types the task itself
+        wait_for_output(&mut events, "got task").await;
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_multi_line_prompt_is_pasted_once_enabled() {
+        let command = script(
+            "paste",
+            "sleep 0.2\nprintf '\\033[?2004hprompt> '\nread line\necho \"got $line\"",
+        );
+        let manager = manager_running(&command);
+        let config = SessionConfig {
+            initial_prompt: Some("first\nsecond".to_string()),
+            ..test_session_config()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        // The line the shell reads starts at the paste marker
+        wait_for_output(&mut events, "got \x1b[200~first").await;
+        assert!(manager.get(&session_id).await.unwrap().bracketed_paste());
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_output_is_byte_exact() {
+        // Enough three byte characters that reads are bound to split some
+        let command = script(
+            "utf8",
+            "i=0\nwhile [ $i -lt 5000 ]; do printf '€'; i=$((i+1)); done",
+        );
+        let manager = manager_running(&command);
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(10), events.recv())
+                .await
+                .expect("timed out waiting for the session to close")
+                .unwrap();
+            if matches!(event, SessionEvent::Closed { .. }) {
+                break;
+            }
+        }
+
+        let buffered = manager.get(&session_id).await.unwrap().get_all_output();
+        assert_eq!(buffered, "€".repeat(5000).into_bytes());
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_session_terminates_process_tree() {
+        let command = script("tree", "sleep 300 &\necho $! started\nwait");
+        let manager = manager_running(&command);
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        let output = wait_for_output(&mut events, "started").await;
+        let grandchild: libc::pid_t = output.split_whitespace().next().unwrap().parse().unwrap();
+
+        let shutdown = manager.close_session(&session_id).await.unwrap();
+
+        assert!(shutdown.clean);
+        assert!(!manager.session_exists(&session_id).await);
+        // The background job is gone, or at most a zombie awaiting its reaper
+        let state = std::fs::read_to_string(format!("/proc/{}/stat", grandchild));
+        assert!(state.map_or(true, |stat| stat.contains(") Z ")));
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_exited_session_leaves_its_process_group_alone() {
+        let manager = manager_running("true");
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let session = manager.get(&session_id).await.unwrap();
+        let mut events = session.subscribe();
+
+        while !session.has_exited() {
+            tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for the session to exit")
+                .unwrap();
+        }
+
+        // The child's pid is free again and could lead someone else's group
+        assert!(SessionManager::signalable_process_groups(&session).is_empty());
+        let shutdown = manager.close_session(&session_id).await.unwrap();
+        assert!(shutdown.clean);
+    }
+
+    #[tokio::test]
+    async fn test_close_session_kills_after_grace_period() {
+        let command = script(
+            "stubborn",
+            "trap '' HUP TERM\necho ready\nwhile :; do sleep 1; done",
+        );
+        let manager = manager_running(&command);
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+        wait_for_output(&mut events, "ready").await;
+
+        let shutdown = manager.close_session(&session_id).await.unwrap();
+
+        assert!(!shutdown.clean);
+        assert!(shutdown.exit_status.is_some());
+        std::fs::remove_file(command).unwrap();
+    }
+
+    /// Scheduler state of a process, as in `ps`
+    fn process_state(pid: libc::pid_t) -> Option<char> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        stat[stat.rfind(')')? + 1..]
+            .split_whitespace()
+            .next()?
+            .chars()
+            .next()
+    }
+
+    async fn wait_for_state(pid: libc::pid_t, expected: impl Fn(Option<char>) -> bool) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while !expected(process_state(pid)) {
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "process {} stuck in state {:?}",
+                pid,
+                process_state(pid)
+            );
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signals_pause_resume_and_interrupt_the_job() {
+        let manager = test_manager();
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let session = manager.get(&session_id).await.unwrap();
+        let pid = session.child_pid().unwrap();
+
+        manager
+            .signal_session(&session_id, SessionSignal::Suspend)
+            .await
+            .unwrap();
+        wait_for_state(pid, |state| state == Some('T')).await;
+        assert_eq!(session.status(), SessionStatus::Paused);
+
+        manager
+            .signal_session(&session_id, SessionSignal::Resume)
+            .await
+            .unwrap();
+        wait_for_state(pid, |state| state != Some('T')).await;
+        assert_ne!(session.status(), SessionStatus::Paused);
+
+        manager
+            .signal_session(&session_id, SessionSignal::Interrupt)
+            .await
+            .unwrap();
+        wait_for_state(pid, |state| matches!(state, None | Some('Z' | 'X'))).await;
+
+        manager.close_session(&session_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_eof_ends_input() {
+        let manager = test_manager();
+        let session_id = manager.create_session(test_session_config()).await.unwrap();
+        let pid = manager.get(&session_id).await.unwrap().child_pid().unwrap();
+
+        // cat reads a line at a time, so EOF on an empty line ends it
+        manager
+            .signal_session(&session_id, SessionSignal::Eof)
+            .await
+            .unwrap();
+        wait_for_state(pid, |state| matches!(state, None | Some('Z' | 'X'))).await;
+
+        manager.close_session(&session_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resize_reaches_the_child() {
+        let command = script("size", "stty size\nread _\nstty size");
+        let manager = manager_running(&command);
+        let config = SessionConfig {
+            size: Some(TerminalSize::new(30, 100)),
+            ..test_session_config()
+        };
+        let session_id = manager.create_session(config).await.unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+        wait_for_output(&mut events, "30 100").await;
+
+        manager
+            .resize(&session_id, TerminalSize::new(50, 132))
+            .await
+            .unwrap();
+        manager
+            .send_input(&session_id, "\n".to_string())
+            .await
+            .unwrap();
+
+        wait_for_output(&mut events, "50 132").await;
+        assert!(manager
+            .resize(&session_id, TerminalSize::new(0, 132))
+            .await
+            .is_err());
+        std::fs::remove_file(command).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_input_to_unknown_session() {
+        let manager = test_manager();
+        let result = manager.send_input("missing", "hello\n".to_string()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_idle_sessions_do_not_tie_up_the_runtime() {
+        const SESSIONS: usize = 64;
+        let config = Config {
+            claude_path: "cat".to_string(),
+            max_sessions: SESSIONS,
+            shutdown_grace_ms: 300,
+            ..Config::default()
+        };
+        let manager = SessionManager::new(Arc::new(config));
+
+        let mut session_ids = Vec::new();
+        for _ in 0..SESSIONS {
+            session_ids.push(manager.create_session(test_session_config()).await.unwrap());
+        }
+
+        // Every session is served by the one runtime thread while the rest idle
+        for session_id in &session_ids {
+            let mut events = manager.subscribe(session_id).await.unwrap();
+            manager
+                .send_input(session_id, format!("{}\n", session_id))
+                .await
+                .unwrap();
+            wait_for_output(&mut events, session_id).await;
+        }
+
+        for session_id in &session_ids {
+            manager.close_session(session_id).await.unwrap();
+        }
     }
 }
@@ -1,14 +1,12 @@
-use crate::config::Config;
-use crate::models::session::{SessionConfig, SessionInfo};
+use crate::models::session::{SessionChange, SessionConfig, SessionInfo, SessionShutdown};
 use crate::session_handler::SessionManager;
-use std::sync::{Arc, OnceLock};
-use tokio::sync::mpsc;
+use std::sync::OnceLock;
+use tokio::sync::{broadcast, mpsc};
 
 #[cfg(feature = "server")]
 pub enum SessionRequest {
     CreateSession {
-        name: String,
-        directory: String,
+        config: SessionConfig,
         response_tx: tokio::sync::oneshot::Sender<Result<String, String>>,
     },
     GetSessions {
@@ -16,46 +14,58 @@ pub enum SessionRequest {
     },
     DeleteSession {
         session_id: String,
-        response_tx: tokio::sync::oneshot::Sender<Result<(), String>>,
+        response_tx: tokio::sync::oneshot::Sender<Result<SessionShutdown, String>>,
+    },
+    /// Subscribe to changes in the set of sessions
+    Watch {
+        response_tx: tokio::sync::oneshot::Sender<broadcast::Receiver<SessionChange>>,
     },
 }
 
 #[cfg(feature = "server")]
 pub static SESSION_REQUEST_TX: OnceLock<mpsc::Sender<SessionRequest>> = OnceLock::new();
 
+/// Serves session requests arriving over a channel from the shared registry
+///
+/// Transports that cannot hold a `SessionManager` talk to this instead; they
+/// see exactly the sessions REST and the WebSocket see.
 #[cfg(feature = "server")]
 pub struct SessionProcessor {
-    config: Arc<Config>,
+    session_manager: SessionManager,
     request_rx: mpsc::Receiver<SessionRequest>,
 }
 
 #[cfg(feature = "server")]
 impl SessionProcessor {
-    pub fn new(config: Arc<Config>) -> (Self, mpsc::Sender<SessionRequest>) {
+    pub fn new(session_manager: SessionManager) -> (Self, mpsc::Sender<SessionRequest>) {
         let (request_tx, request_rx) = mpsc::channel(100);
 
-        (Self { config, request_rx }, request_tx)
+        (
+            Self {
+                session_manager,
+                request_rx,
+            },
+            request_tx,
+        )
     }
 
     pub async fn run(mut self) {
         while let Some(request) = self.request_rx.recv().await {
             match request {
                 SessionRequest::CreateSession {
-                    name,
-                    directory,
+                    config,
                     response_tx,
                 } => {
-                    let session_manager = SessionManager::new(Arc::clone(&self.config));
-                    let session_config = SessionConfig { name, directory };
-                    let result = session_manager
-                        .create_session(session_config)
+                    let result = self
+                        .session_manager
+                        .create_session(config)
                         .await
                         .map_err(|e| e.to_string());
                     let _ = response_tx.send(result);
                 }
                 SessionRequest::GetSessions { response_tx } => {
-                    let session_manager = SessionManager::new(Arc::clone(&self.config));
-                    let result = session_manager
+                    let result = self
+                        .session_manager
                         .list_sessions()
                         .await
                         .map_err(|e| e.to_string());
@@ -65,14 +75,84 @@ impl SessionProcessor {
                     session_id,
                     response_tx,
                 } => {
-                    let session_manager = SessionManager::new(Arc::clone(&self.config));
-                    let result = session_manager
-                        .close_session(&session_id)
-                        .await
-                        .map_err(|e| e.to_string());
-                    let _ = response_tx.send(result);
+                    // Closing waits out the grace period, so don't hold up
+                    // other requests behind it
+                    let session_manager = self.session_manager.clone();
+                    tokio::spawn(async move {
+                        let result = session_manager
+                            .close_session(&session_id)
+                            .await
+                            .map_err(|e| e.to_string());
+                        let _ = response_tx.send(result);
+                    });
+                }
+                SessionRequest::Watch { response_tx } => {
+                    let _ = response_tx.send(self.session_manager.watch());
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_processor_serves_the_shared_registry() {
+        let config = Config {
+            claude_path: "cat".to_string(),
+            shutdown_grace_ms: 300,
+            ..Config::default()
+        };
+        let session_manager = SessionManager::new(Arc::new(config));
+        let (processor, request_tx) = SessionProcessor::new(session_manager.clone());
+        tokio::spawn(processor.run());
+
+        let (response_tx, response_rx) = oneshot::channel();
+        request_tx
+            .send(SessionRequest::Watch { response_tx })
+            .await
+            .unwrap();
+        let mut changes = response_rx.await.unwrap();
+
+        let (response_tx, response_rx) = oneshot::channel();
+        let config = SessionConfig {
+            name: "Test Session".to_string(),
+            directory: "/tmp".to_string(),
+            size: None,
+            initial_prompt: None,
+        };
+        request_tx
+            .send(SessionRequest::CreateSession {
+                config,
+                response_tx,
+            })
+            .await
+            .unwrap();
+        let session_id = response_rx.await.unwrap().unwrap();
+
+        // Created through the processor, visible to everyone else
+        assert!(session_manager.session_exists(&session_id).await);
+        match changes.recv().await.unwrap() {
+            SessionChange::Created(info) => assert_eq!(info.id, session_id),
+            other => panic!("unexpected change {:?}", other),
+        }
+
+        session_manager.close_session(&session_id).await.unwrap();
+
+        let (response_tx, response_rx) = oneshot::channel();
+        request_tx
+            .send(SessionRequest::GetSessions { response_tx })
+            .await
+            .unwrap();
+        assert!(response_rx.await.unwrap().unwrap().is_empty());
+        assert_eq!(
+            changes.recv().await.unwrap(),
+            SessionChange::Closed { session_id }
+        );
+    }
+}
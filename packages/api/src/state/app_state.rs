@@ -1,69 +1,43 @@
 use crate::config::Config;
-use crate::models::session::SessionMetadata;
 use crate::session_handler::SessionManager;
-use dioxus::prelude::*;
-use std::collections::HashMap;
+use crate::session_processor::{SessionProcessor, SESSION_REQUEST_TX};
 use std::sync::{Arc, OnceLock};
-use tokio::sync::RwLock;
 
 /// Global app state instance
 static GLOBAL_APP_STATE: OnceLock<AppState> = OnceLock::new();
 
 #[derive(Clone, Debug)]
 pub struct AppState {
+    /// The one session registry, shared by every transport
     pub session_manager: SessionManager,
     pub config: Arc<Config>,
-    pub session_metadata_store: SessionMetadataStore,
-}
-
-/// Thread-safe session metadata store for WebSocket access
-#[derive(Clone, Debug)]
-pub struct SessionMetadataStore {
-    metadata: Arc<RwLock<HashMap<String, SessionMetadata>>>,
-}
-
-impl SessionMetadataStore {
-    pub fn new() -> Self {
-        Self {
-            metadata: Arc::new(RwLock::new(HashMap::new())),
-        }
-    }
-
-    pub async fn session_exists(&self, session_id: &str) -> bool {
-        let metadata = self.metadata.read().await;
-        metadata.contains_key(session_id)
-    }
-
-    pub async fn update_metadata(&self, session_id: &str, metadata: SessionMetadata) {
-        let mut store = self.metadata.write().await;
-        store.insert(session_id.to_string(), metadata);
-    }
-
-    pub async fn remove_metadata(&self, session_id: &str) {
-        let mut store = self.metadata.write().await;
-        store.remove(session_id);
-    }
 }
 
 impl AppState {
     pub fn new(config: Config) -> Self {
         let config = Arc::new(config);
-        let mut session_manager = SessionManager::new(Arc::clone(&config));
-        let session_metadata_store = SessionMetadataStore::new();
-
-        // Set the metadata store on the session manager
-        session_manager.set_metadata_store(session_metadata_store.clone());
+        let session_manager = SessionManager::new(Arc::clone(&config));
 
         Self {
             session_manager,
             config,
-            session_metadata_store,
         }
     }
 
     /// Initialize the global app state (call once on server startup)
+    ///
+    /// When called inside a tokio runtime this also starts the session
+    /// request processor behind `SESSION_REQUEST_TX`.
     pub fn init(config: Config) {
         let state = Self::new(config);
+
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let (processor, request_tx) = SessionProcessor::new(state.session_manager.clone());
+            if SESSION_REQUEST_TX.set(request_tx).is_ok() {
+                runtime.spawn(processor.run());
+            }
+        }
+
         GLOBAL_APP_STATE.set(state).ok();
     }
 
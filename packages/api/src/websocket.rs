@@ -1,8 +1,421 @@
-use crate::models::message::{ClientEvent, ServerEvent};
-use crate::state::app_state::SessionMetadataStore;
-use crate::AppState;
-use dioxus::fullstack::{TypedWebsocket, WebSocketOptions, Websocket};
+use crate::models::message::{
+    Capability, ClientEvent, FrameKind, HeartbeatSettings, OutputEncoding, OutputFrame,
+    ProtocolError, ProtocolErrorKind, ServerEvent, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::models::session::{Attachment, SessionChange, SessionEvent};
+use crate::models::utf8::Utf8Decoder;
+use crate::outgoing::{Outgoing, OutgoingQueue};
+use crate::{AppState, Config};
+use anyhow::Context;
+use dioxus::fullstack::{Message, TypedWebsocket, WebSocketOptions, Websocket, WebsocketError};
 use dioxus::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// Protocol features this server supports, announced in `Welcome`
+const SERVER_CAPABILITIES: &[Capability] = &[
+    Capability::BinaryOutput,
+    Capability::Resume,
+    Capability::Heartbeat,
+    Capability::Resync,
+    Capability::RawKeys,
+    Capability::Signals,
+];
+
+/// Text encoding state for one session's output
+#[derive(Debug, Default)]
+struct TextStream {
+    decoder: Utf8Decoder,
+    /// Stream offset just past the bytes fed to the decoder
+    offset: u64,
+}
+
+impl TextStream {
+    /// Stream offset just past the text decoded so far
+    fn decoded_offset(&self) -> u64 {
+        self.offset - self.decoder.pending().len() as u64
+    }
+}
+
+/// Per-socket state: the sessions this client subscribed to
+///
+/// Only subscribed sessions send output down the socket. Each attachment is
+/// a task forwarding session events into the socket's outgoing queue, so a
+/// session never waits on a particular client. Registry changes reach every
+/// client the same way.
+struct ClientConnection {
+    outgoing: OutgoingQueue,
+    attachments: HashMap<String, JoinHandle<()>>,
+    session_watch: Option<JoinHandle<()>>,
+    encoding: OutputEncoding,
+    /// Per-session decoders for text encoding, holding split characters
+    text_streams: HashMap<String, TextStream>,
+    /// Protocol version agreed in the handshake
+    protocol_version: u32,
+    /// Optional features the client declared in `Hello`
+    capabilities: Vec<Capability>,
+}
+
+impl ClientConnection {
+    fn new(queue_bytes: usize) -> (Self, OutgoingQueue) {
+        let outgoing = OutgoingQueue::new(queue_bytes);
+        let connection = Self {
+            outgoing: outgoing.clone(),
+            attachments: HashMap::new(),
+            session_watch: None,
+            encoding: OutputEncoding::default(),
+            text_streams: HashMap::new(),
+            // Until the client says hello
+            protocol_version: 1,
+            capabilities: Vec::new(),
+        };
+        (connection, outgoing)
+    }
+
+    /// Whether the client declared `capability` in its `Hello`
+    fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Switch output encoding and acknowledge it to the client
+    ///
+    /// Characters split across reads wait in the text decoders; when moving
+    /// to binary frames they are handed over as raw bytes after the
+    /// acknowledgement.
+    fn set_encoding(&mut self, encoding: OutputEncoding) -> anyhow::Result<Vec<Message>> {
+        self.encoding = encoding;
+        let mut messages = self.encode(Outgoing::Event(ServerEvent::OutputEncodingChanged {
+            encoding,
+        }))?;
+
+        if encoding == OutputEncoding::Binary {
+            for (session_id, mut stream) in self.text_streams.drain() {
+                let data = stream.decoder.take_pending();
+                if !data.is_empty() {
+                    let frame = OutputFrame {
+                        kind: FrameKind::Output,
+                        session_id,
+                        offset: stream.offset,
+                        data,
+                    };
+                    messages.push(Message::Binary(frame.encode().into()));
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Turn a queued item into the WebSocket messages for this client
+    ///
+    /// Text decoding yields nothing while it waits on the rest of a
+    /// character, and reports offsets up to the last whole character.
+    fn encode(&mut self, item: Outgoing) -> anyhow::Result<Vec<Message>> {
+        let event = match item {
+            Outgoing::SetEncoding(encoding) => return self.set_encoding(encoding),
+            Outgoing::Restart { session_id } => {
+                self.text_streams.remove(&session_id);
+                return Ok(Vec::new());
+            }
+            Outgoing::Event(event) => {
+                if let ServerEvent::SessionClosed { session_id, .. }
+                | ServerEvent::SessionRemoved { session_id }
+                | ServerEvent::ResyncNeeded { session_id } = &event
+                {
+                    self.text_streams.remove(session_id);
+                }
+                event
+            }
+            Outgoing::Output {
+                kind,
+                session_id,
+                offset,
+                data,
+            } => match self.encoding {
+                OutputEncoding::Binary => {
+                    let frame = OutputFrame {
+                        kind,
+                        session_id,
+                        offset,
+                        data,
+                    };
+                    return Ok(vec![Message::Binary(frame.encode().into())]);
+                }
+                OutputEncoding::Text => {
+                    let stream = self.text_streams.entry(session_id.clone()).or_default();
+                    if kind == FrameKind::History {
+                        // A snapshot restarts the stream for this session
+                        *stream = TextStream::default();
+                    }
+
+                    let data = stream.decoder.decode(&data);
+                    stream.offset = offset;
+                    let offset = stream.decoded_offset();
+                    match kind {
+                        FrameKind::History => ServerEvent::SessionHistory {
+                            session_id,
+                            data,
+                            offset,
+                        },
+                        FrameKind::Output if data.is_empty() => return Ok(Vec::new()),
+                        FrameKind::Output => ServerEvent::TerminalOutput {
+                            session_id,
+                            data,
+                            offset,
+                        },
+                    }
+                }
+            },
+        };
+
+        let json = serde_json::to_vec(&event).context("Failed to serialize server event")?;
+        Ok(vec![Message::Binary(json.into())])
+    }
+
+    /// Start forwarding a session's output to this client
+    ///
+    /// The scrollback is queued first and any existing attachment is
+    /// replaced, so the client sees history followed by live output without
+    /// gaps or duplicates. A client resuming after stream offset `after`
+    /// only gets what followed it, as long as that is still buffered.
+    async fn subscribe(
+        &mut self,
+        session_id: &str,
+        after: Option<u64>,
+        app_state: &AppState,
+    ) -> anyhow::Result<()> {
+        // Wait for the old forwarder to stop so it cannot queue output the
+        // new history snapshot already contains
+        self.unsubscribe(session_id).await;
+
+        let Attachment {
+            history,
+            offset,
+            resumed,
+            ready,
+            mut events,
+        } = app_state.session_manager.attach(session_id, after).await?;
+
+        if resumed {
+            tracing::debug!(
+                "Resuming session {} with {} bytes after offset {:?}",
+                session_id,
+                history.len(),
+                after
+            );
+            self.outgoing.push(Outgoing::Restart {
+                session_id: session_id.to_string(),
+            });
+            if !history.is_empty() {
+                self.outgoing.push(Outgoing::Output {
+                    kind: FrameKind::Output,
+                    session_id: session_id.to_string(),
+                    offset,
+                    data: history,
+                });
+            }
+        } else {
+            tracing::debug!(
+                "Sending a {} byte screen snapshot for session {}",
+                history.len(),
+                session_id
+            );
+            self.outgoing.push(Outgoing::Output {
+                kind: FrameKind::History,
+                session_id: session_id.to_string(),
+                offset,
+                data: history,
+            });
+        }
+        if ready {
+            self.outgoing
+                .push(Outgoing::Event(ServerEvent::SessionReady {
+                    session_id: session_id.to_string(),
+                }));
+        }
+
+        let outgoing = self.outgoing.clone();
+        let forward_session_id = session_id.to_string();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let item = match events.recv().await {
+                    Ok(SessionEvent::Output { offset, data }) => {
+                        outgoing.push_output(&forward_session_id, offset, data);
+                        continue;
+                    }
+                    Ok(SessionEvent::Ready) => Outgoing::Event(ServerEvent::SessionReady {
+                        session_id: forward_session_id.clone(),
+                    }),
+                    Ok(SessionEvent::Closed { reason }) => {
+                        Outgoing::Event(ServerEvent::SessionClosed {
+                            session_id: forward_session_id.clone(),
+                            reason: Some(reason),
+                        })
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "Client fell behind on session {}, dropped {} events",
+                            forward_session_id,
+                            skipped
+                        );
+                        outgoing.resync(&forward_session_id);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                outgoing.push(item);
+            }
+        });
+
+        tracing::debug!("Client subscribed to session {}", session_id);
+        self.attachments.insert(session_id.to_string(), handle);
+        Ok(())
+    }
+
+    /// Stop forwarding a session's output, returning whether it was subscribed
+    async fn unsubscribe(&mut self, session_id: &str) -> bool {
+        let Some(handle) = self.attachments.remove(session_id) else {
+            return false;
+        };
+        handle.abort();
+        let _ = handle.await;
+        // Output already queued must not reach a client that left
+        self.outgoing.discard(session_id);
+        tracing::debug!("Client unsubscribed from session {}", session_id);
+        true
+    }
+
+    /// Bring a client that fell behind on a session back in step, for
+    /// clients that cannot be asked to resync themselves
+    async fn catch_up(&mut self, session_id: &str, app_state: &AppState) -> anyhow::Result<()> {
+        if !self.attachments.contains_key(session_id) {
+            // Unsubscribed meanwhile, nothing to catch up on
+            self.outgoing.resume(session_id);
+            return Ok(());
+        }
+        tracing::debug!(
+            "Sending session {} history to a client that fell behind",
+            session_id
+        );
+        self.subscribe(session_id, None, app_state).await
+    }
+
+    /// Keep this client's session list in step with the registry
+    ///
+    /// The list is taken after subscribing, so no change falls between the
+    /// two; changes the list already reflects are no-ops for the client.
+    /// A client that falls behind gets a fresh list instead of the changes
+    /// it missed.
+    async fn watch_sessions(&mut self, app_state: &AppState) -> anyhow::Result<()> {
+        let session_manager = app_state.session_manager.clone();
+        let mut changes = session_manager.watch();
+        let sessions = session_manager.list_sessions().await?;
+        self.outgoing
+            .push(Outgoing::Event(session_list_event(sessions)));
+
+        let outgoing = self.outgoing.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                let event = match changes.recv().await {
+                    Ok(change) => match change_event(change) {
+                        Some(event) => event,
+                        None => continue,
+                    },
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "Client fell behind on session changes, resending list after {} missed",
+                            skipped
+                        );
+                        match session_manager.list_sessions().await {
+                            Ok(sessions) => session_list_event(sessions),
+                            Err(e) => {
+                                tracing::error!("Failed to list sessions: {:#}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                outgoing.push(Outgoing::Event(event));
+            }
+        });
+
+        self.session_watch = Some(handle);
+        Ok(())
+    }
+}
+
+impl Drop for ClientConnection {
+    fn drop(&mut self) {
+        for handle in self.attachments.values().chain(&self.session_watch) {
+            handle.abort();
+        }
+    }
+}
+
+/// Liveness of the client on the other end of a socket
+///
+/// Behind proxies a vanished client can leave a half-open connection that
+/// never errors, so the server pings and gives up on clients that stay
+/// silent for too long.
+struct Heartbeat {
+    timeout: Duration,
+    last_seen: Instant,
+    nonce: u64,
+}
+
+impl Heartbeat {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_seen: Instant::now(),
+            nonce: 0,
+        }
+    }
+
+    /// Note that the client was heard from
+    fn seen(&mut self, now: Instant) {
+        self.last_seen = now;
+    }
+
+    /// Nonce for the next ping, or `None` once the client has been silent
+    /// for longer than the timeout
+    fn next_ping(&mut self, now: Instant) -> Option<u64> {
+        if now.duration_since(self.last_seen) > self.timeout {
+            return None;
+        }
+        self.nonce += 1;
+        Some(self.nonce)
+    }
+}
+
+fn session_list_event(sessions: Vec<crate::models::session::SessionInfo>) -> ServerEvent {
+    ServerEvent::SessionList {
+        sessions: sessions.into_iter().map(Into::into).collect(),
+    }
+}
+
+/// The event announcing a registry change to clients, if any
+fn change_event(change: SessionChange) -> Option<ServerEvent> {
+    match change {
+        SessionChange::Created(info) => Some(ServerEvent::SessionAdded {
+            session: info.into(),
+        }),
+        SessionChange::StatusChanged { session_id, status } => {
+            Some(ServerEvent::SessionStatusChanged { session_id, status })
+        }
+        SessionChange::Closed { session_id } => Some(ServerEvent::SessionRemoved { session_id }),
+        // Attached clients learn of it in order with the session's output,
+        // everyone else from the status change that follows
+        SessionChange::Exited { .. } => None,
+    }
+}
 
 /// Terminal WebSocket server function
 ///
@@ -18,7 +431,7 @@ pub async fn terminal_websocket(
         tracing::info!("WebSocket client connected for terminal I/O");
 
         // Get the global app state
-        let app_state = match std::panic::catch_unwind(|| AppState::global()) {
+        let app_state = match std::panic::catch_unwind(AppState::global) {
             Ok(state) => state,
             Err(_) => {
                 tracing::error!("Failed to get AppState - not initialized");
@@ -31,124 +444,782 @@ pub async fn terminal_websocket(
             }
         };
 
-        // Handle incoming terminal I/O messages from client
-        while let Ok(event) = socket.recv().await {
-            tracing::debug!("Received client event: {:?}", event);
-            match handle_client_event(event, &mut socket, &app_state.session_metadata_store).await {
-                Ok(_) => {}
-                Err(e) => {
-                    tracing::error!("Error handling client event: {}", e);
-                    let _ = socket
-                        .send(ServerEvent::Error {
-                            message: e.to_string(),
-                        })
-                        .await;
+        let (mut connection, outgoing) = ClientConnection::new(app_state.config.client_queue_bytes);
+        if let Err(e) = connection.watch_sessions(&app_state).await {
+            tracing::error!("Failed to watch sessions: {:#}", e);
+        }
+
+        let settings = heartbeat_settings(&app_state.config);
+        let heartbeat_timeout = Duration::from_secs(settings.timeout_secs);
+        let mut heartbeat = Heartbeat::new(heartbeat_timeout);
+        let mut ping_interval = tokio::time::interval(Duration::from_secs(settings.interval_secs));
+        ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // Output arriving while a frame waits is merged into it
+        let frame_interval = Duration::from_millis(app_state.config.output_frame_interval_ms);
+        let mut next_frame_at = Instant::now();
+
+        // Handle incoming terminal I/O messages and forward session output
+        loop {
+            let throttled = Instant::now() < next_frame_at;
+            tokio::select! {
+                received = socket.recv_raw() => {
+                    let payload = match received {
+                        Ok(Message::Text(text)) => text.into_bytes().into(),
+                        Ok(Message::Binary(bytes)) => bytes,
+                        Ok(Message::Ping(_) | Message::Pong(_)) => {
+                            heartbeat.seen(Instant::now());
+                            continue;
+                        }
+                        Ok(Message::Close { .. }) | Err(_) => break,
+                    };
+                    heartbeat.seen(Instant::now());
+
+                    // Events this build does not understand are answered,
+                    // not silently dropped
+                    let event = match ClientEvent::decode(&payload) {
+                        Ok(event) => event,
+                        Err(error) => {
+                            tracing::warn!("Rejected client message: {}", error.message);
+                            outgoing.push(Outgoing::Event(ServerEvent::ProtocolError(error)));
+                            continue;
+                        }
+                    };
+
+                    tracing::debug!("Received client event: {:?}", event);
+                    if let Err(e) = handle_client_event(event, &mut connection, &app_state).await {
+                        tracing::error!("Error handling client event: {:#}", e);
+                        outgoing.push(Outgoing::Event(ServerEvent::Error {
+                            message: format!("{:#}", e),
+                        }));
+                    }
+                }
+
+                _ = tokio::time::sleep_until(next_frame_at), if throttled => {}
+
+                item = outgoing.pop(), if !throttled => {
+                    if let Outgoing::Event(ServerEvent::ResyncNeeded { session_id }) = &item {
+                        if !connection.supports(Capability::Resync) {
+                            if let Err(e) = connection.catch_up(session_id, &app_state).await {
+                                tracing::error!("Failed to catch up on session: {:#}", e);
+                            }
+                            continue;
+                        }
+                    }
+                    if item.is_live_output() {
+                        next_frame_at = Instant::now() + frame_interval;
+                    }
+                    let messages = match connection.encode(item) {
+                        Ok(messages) => messages,
+                        Err(e) => {
+                            tracing::error!("Error encoding server event: {:#}", e);
+                            continue;
+                        }
+                    };
+                    // A client that stopped reading must not stall us forever
+                    match tokio::time::timeout(
+                        heartbeat_timeout,
+                        send_messages(&mut socket, messages),
+                    )
+                    .await
+                    {
+                        Ok(Ok(())) => {}
+                        Ok(Err(_)) => break,
+                        Err(_) => {
+                            tracing::warn!("Client stopped reading, dropping the connection");
+                            break;
+                        }
+                    }
+                }
+
+                _ = ping_interval.tick() => {
+                    // Clients without heartbeats neither answer pings nor
+                    // ping us
+                    if !connection.supports(Capability::Heartbeat) {
+                        continue;
+                    }
+                    let Some(nonce) = heartbeat.next_ping(Instant::now()) else {
+                        tracing::warn!(
+                            "No heartbeat from client for {:?}, dropping the connection",
+                            heartbeat_timeout
+                        );
+                        break;
+                    };
+                    outgoing.push(Outgoing::Event(ServerEvent::Ping { nonce }));
                 }
             }
         }
 
+        // Stops forwarding every session this client subscribed to
+        drop(connection);
         tracing::info!("WebSocket client disconnected");
     }))
 }
 
+async fn send_messages(
+    socket: &mut TypedWebsocket<ClientEvent, ServerEvent>,
+    messages: Vec<Message>,
+) -> Result<(), WebsocketError> {
+    for message in messages {
+        socket.send_raw(message).await?;
+    }
+    Ok(())
+}
+
 /// Handle a single client event (terminal I/O only)
+///
+/// Replies are queued behind whatever the connection already has waiting,
+/// so they reach the client in order with it.
 async fn handle_client_event(
     event: ClientEvent,
-    socket: &mut TypedWebsocket<ClientEvent, ServerEvent>,
-    metadata_store: &SessionMetadataStore,
+    connection: &mut ClientConnection,
+    app_state: &AppState,
 ) -> anyhow::Result<()> {
     match event {
+        ClientEvent::Hello {
+            protocol_version,
+            capabilities,
+        } => {
+            tracing::info!(
+                "Client speaks protocol version {} with {:?}",
+                protocol_version,
+                capabilities
+            );
+            let heartbeat = heartbeat_settings(&app_state.config);
+            let reply = welcome(protocol_version, heartbeat);
+            if let ServerEvent::Welcome {
+                protocol_version, ..
+            } = reply
+            {
+                connection.protocol_version = protocol_version;
+                connection.capabilities = capabilities;
+            }
+            connection.outgoing.push(Outgoing::Event(reply));
+        }
+
         ClientEvent::SendInput { session_id, input } => {
             tracing::info!("Sending input to session {}", session_id);
-            handle_send_input(session_id, input, socket, metadata_store).await?;
+            // Version 1 typed the input and submitted it
+            let submit = connection.protocol_version < 2;
+            handle_send_input(session_id, input, submit, connection, app_state).await?;
+        }
+
+        ClientEvent::SendKeys { session_id, keys } => {
+            // Every keystroke in raw mode ends up here, so keep it quiet
+            tracing::debug!("Sending {} key bytes to session {}", keys.len(), session_id);
+            handle_send_keys(session_id, keys, connection, app_state).await?;
+        }
+
+        ClientEvent::Signal { session_id, signal } => {
+            tracing::info!("Sending {:?} to session {}", signal, session_id);
+            app_state
+                .session_manager
+                .signal_session(&session_id, signal)
+                .await
+                .with_context(|| format!("Failed to signal session {}", session_id))?;
+        }
+
+        ClientEvent::Resize { session_id, size } => {
+            tracing::debug!(
+                "Resizing session {} to {}x{}",
+                session_id,
+                size.rows,
+                size.cols
+            );
+            app_state
+                .session_manager
+                .resize(&session_id, size)
+                .await
+                .with_context(|| format!("Failed to resize session {}", session_id))?;
+        }
+
+        ClientEvent::Subscribe { session_id, after } => {
+            tracing::info!("Subscribing to session {}", session_id);
+            // Only clients that can resume know what to do with the rest of
+            // a stream
+            let after = after.filter(|_| connection.supports(Capability::Resume));
+            handle_subscribe(session_id, after, connection, app_state).await?;
         }
 
         ClientEvent::RequestHistory { session_id } => {
             tracing::info!("Requesting history for session {}", session_id);
-            handle_request_history(session_id, socket, metadata_store).await?;
+            handle_subscribe(session_id, None, connection, app_state).await?;
+        }
+
+        ClientEvent::Unsubscribe { session_id } => {
+            if !connection.unsubscribe(&session_id).await {
+                tracing::debug!("Unsubscribe from unsubscribed session {}", session_id);
+            }
+        }
+
+        ClientEvent::SetOutputEncoding { mut encoding } => {
+            if encoding == OutputEncoding::Binary && !connection.supports(Capability::BinaryOutput)
+            {
+                // Acknowledged as text, which is what the client keeps getting
+                tracing::warn!("Client asked for binary output without declaring it");
+                encoding = OutputEncoding::Text;
+            }
+            tracing::debug!("Client switched output encoding to {:?}", encoding);
+            // Applied in order with queued output, so the acknowledgement
+            // marks exactly where the new encoding starts
+            connection.outgoing.push(Outgoing::SetEncoding(encoding));
+        }
+
+        ClientEvent::Ping { nonce } => {
+            connection
+                .outgoing
+                .push(Outgoing::Event(ServerEvent::Pong { nonce }));
         }
 
+        // Receiving it was the point
+        ClientEvent::Pong { .. } => {}
+
         // Session management must use REST API
         ClientEvent::CreateSession { .. } | ClientEvent::CloseSession { .. } => {
             tracing::warn!("Rejected session management event on WebSocket - use REST API");
-            socket
-                .send(ServerEvent::Error {
+            connection
+                .outgoing
+                .push(Outgoing::Event(ServerEvent::Error {
                     message: "Session management must use REST API, not WebSocket".to_string(),
-                })
-                .await?;
+                }));
         }
     }
 
     Ok(())
 }
 
-/// Handle sending input to a session's PTY
+/// The heartbeat schedule configured for this server
+///
+/// The timeout is kept to at least two intervals, so a peer that keeps to
+/// the schedule is never dropped for a single late ping.
+fn heartbeat_settings(config: &Config) -> HeartbeatSettings {
+    let interval_secs = config.heartbeat_interval_secs.max(1);
+    HeartbeatSettings {
+        interval_secs,
+        timeout_secs: config.heartbeat_timeout_secs.max(interval_secs * 2),
+    }
+}
+
+/// The answer to a client announcing `protocol_version`
+///
+/// Newer clients are expected to fall back to the server's version; older
+/// ones are served in theirs as long as it is still supported.
+fn welcome(protocol_version: u32, heartbeat: HeartbeatSettings) -> ServerEvent {
+    if protocol_version < MIN_PROTOCOL_VERSION {
+        return ServerEvent::ProtocolError(ProtocolError {
+            kind: ProtocolErrorKind::UnsupportedVersion,
+            event_type: Some("Hello".to_string()),
+            message: format!(
+                "Protocol version {} is no longer supported, this server speaks {} to {}",
+                protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+        });
+    }
+
+    ServerEvent::Welcome {
+        protocol_version: protocol_version.min(PROTOCOL_VERSION),
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: SERVER_CAPABILITIES.to_vec(),
+        heartbeat,
+    }
+}
+
+/// Handle typing text into a session's PTY, as a paste when it asks for one,
+/// pressing Enter after it if `submit` is set
 async fn handle_send_input(
     session_id: String,
     input: String,
-    socket: &mut TypedWebsocket<ClientEvent, ServerEvent>,
-    metadata_store: &SessionMetadataStore,
+    submit: bool,
+    connection: &mut ClientConnection,
+    app_state: &AppState,
 ) -> anyhow::Result<()> {
     // Check if session exists
-    if !metadata_store.session_exists(&session_id).await {
+    if !app_state.session_manager.session_exists(&session_id).await {
         tracing::warn!("Input for non-existent session: {}", session_id);
-        socket
-            .send(ServerEvent::Error {
+        connection
+            .outgoing
+            .push(Outgoing::Event(ServerEvent::Error {
                 message: format!("Session {} not found", session_id),
-            })
-            .await?;
+            }));
         return Ok(());
     }
 
-    // TODO: Send input to the actual PTY process
-    // This requires:
-    // 1. Get the PTY handle from session_manager
-    // 2. Write input to PTY's stdin
-    // 3. PTY output will be streamed back via a separate task
+    let manager = &app_state.session_manager;
+    // The newline the client typed to submit is pressed as Enter instead
+    let text = if submit {
+        input.trim_end_matches(['\r', '\n'])
+    } else {
+        &input
+    };
+    // Output produced in response reaches whoever subscribed to the session
+    manager
+        .paste_input(&session_id, text)
+        .await
+        .with_context(|| format!("Failed to send input to session {}", session_id))?;
+    if submit {
+        manager
+            .send_input(&session_id, "\r".to_string())
+            .await
+            .with_context(|| format!("Failed to submit input to session {}", session_id))?;
+    }
+
+    Ok(())
+}
+
+/// Handle writing keystrokes to a session's PTY exactly as given
+async fn handle_send_keys(
+    session_id: String,
+    keys: String,
+    connection: &mut ClientConnection,
+    app_state: &AppState,
+) -> anyhow::Result<()> {
+    if !app_state.session_manager.session_exists(&session_id).await {
+        tracing::warn!("Keys for non-existent session: {}", session_id);
+        connection
+            .outgoing
+            .push(Outgoing::Event(ServerEvent::Error {
+                message: format!("Session {} not found", session_id),
+            }));
+        return Ok(());
+    }
 
-    // Mock implementation for testing
-    tracing::debug!("Mock echo for session {}: {}", session_id, input.trim());
-    socket
-        .send(ServerEvent::TerminalOutput {
-            session_id,
-            data: format!("> {}\n", input.trim()),
-        })
-        .await?;
+    app_state
+        .session_manager
+        .send_input(&session_id, keys)
+        .await
+        .with_context(|| format!("Failed to send keys to session {}", session_id))?;
 
     Ok(())
 }
 
-/// Handle requesting buffered session history
-async fn handle_request_history(
+/// Handle subscribing to a session's history and live output
+async fn handle_subscribe(
     session_id: String,
-    socket: &mut TypedWebsocket<ClientEvent, ServerEvent>,
-    metadata_store: &SessionMetadataStore,
+    after: Option<u64>,
+    connection: &mut ClientConnection,
+    app_state: &AppState,
 ) -> anyhow::Result<()> {
     // Check if session exists
-    if !metadata_store.session_exists(&session_id).await {
-        tracing::warn!("History requested for non-existent session: {}", session_id);
-        socket
-            .send(ServerEvent::Error {
+    if !app_state.session_manager.session_exists(&session_id).await {
+        tracing::warn!("Subscribe to non-existent session: {}", session_id);
+        connection
+            .outgoing
+            .push(Outgoing::Event(ServerEvent::Error {
                 message: format!("Session {} not found", session_id),
-            })
-            .await?;
+            }));
         return Ok(());
     }
 
-    // TODO: Retrieve buffered history from session
-    // This requires:
-    // 1. Get the session from session_manager
-    // 2. Retrieve buffered output (scrollback buffer)
-    // 3. Send all buffered lines as TerminalOutput events
-
-    // Mock implementation for testing
-    tracing::debug!("Mock history for session {}", session_id);
-    socket
-        .send(ServerEvent::TerminalOutput {
-            session_id,
-            data: "Session started...\n".to_string(),
-        })
-        .await?;
+    connection.subscribe(&session_id, after, app_state).await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::session::{SessionConfig, SessionStatus};
+    use crate::outgoing::DEFAULT_CLIENT_QUEUE_BYTES;
+
+    fn output(session_id: &str, offset: u64, data: &[u8]) -> Outgoing {
+        Outgoing::Output {
+            kind: FrameKind::Output,
+            session_id: session_id.to_string(),
+            offset,
+            data: data.to_vec(),
+        }
+    }
+
+    fn decode_event(message: &Message) -> ServerEvent {
+        match message {
+            Message::Binary(bytes) => serde_json::from_slice(bytes).unwrap(),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_subscribed_sessions_send_output_and_resume() {
+        let app_state = AppState::new(Config {
+            claude_path: "cat".to_string(),
+            shutdown_grace_ms: 300,
+            ..Config::default()
+        });
+        let manager = &app_state.session_manager;
+        let config = SessionConfig {
+            name: "Test Session".to_string(),
+            directory: "/tmp".to_string(),
+            size: None,
+            initial_prompt: None,
+        };
+        let watched = manager.create_session(config.clone()).await.unwrap();
+        let other = manager.create_session(config).await.unwrap();
+
+        let (mut connection, rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        connection
+            .subscribe(&watched, None, &app_state)
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.pop().await,
+            Outgoing::Output {
+                kind: FrameKind::History,
+                ..
+            }
+        ));
+
+        manager.send_input(&other, "other\n".into()).await.unwrap();
+        manager.send_input(&watched, "mine\n".into()).await.unwrap();
+        let mut received = Vec::new();
+        let mut last_offset = 0;
+        while !String::from_utf8_lossy(&received).contains("mine") {
+            let item = tokio::time::timeout(Duration::from_secs(5), rx.pop())
+                .await
+                .expect("timed out waiting for output");
+            if let Outgoing::Output {
+                session_id,
+                offset,
+                data,
+                ..
+            } = item
+            {
+                assert_eq!(session_id, watched);
+                assert_eq!(offset, last_offset + data.len() as u64);
+                last_offset = offset;
+                received.extend(data);
+            }
+        }
+
+        assert!(connection.unsubscribe(&watched).await);
+        manager
+            .send_input(&watched, "after\n".into())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        while let Some(item) = rx.try_pop() {
+            if let Outgoing::Output {
+                session_id,
+                offset,
+                data,
+                ..
+            } = item
+            {
+                assert_eq!(session_id, watched);
+                assert!(!String::from_utf8_lossy(&data).contains("after"));
+                last_offset = offset;
+            }
+        }
+
+        // Resuming picks up exactly what was missed, as regular output
+        connection
+            .subscribe(&watched, Some(last_offset), &app_state)
+            .await
+            .unwrap();
+        assert!(matches!(rx.pop().await, Outgoing::Restart { .. }));
+        match rx.pop().await {
+            Outgoing::Output {
+                kind: FrameKind::Output,
+                offset,
+                data,
+                ..
+            } => {
+                assert!(String::from_utf8_lossy(&data).contains("after"));
+                assert_eq!(offset, last_offset + data.len() as u64);
+            }
+            other => panic!("unexpected item {:?}", other),
+        }
+
+        manager.close_session(&watched).await.unwrap();
+        manager.close_session(&other).await.unwrap();
+    }
+
+    #[test]
+    fn test_heartbeat_gives_up_on_silent_clients() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new(Duration::from_secs(45));
+        heartbeat.seen(start);
+
+        assert_eq!(
+            heartbeat.next_ping(start + Duration::from_secs(15)),
+            Some(1)
+        );
+        assert_eq!(
+            heartbeat.next_ping(start + Duration::from_secs(30)),
+            Some(2)
+        );
+
+        // An answer buys the client another timeout
+        heartbeat.seen(start + Duration::from_secs(40));
+        assert_eq!(
+            heartbeat.next_ping(start + Duration::from_secs(80)),
+            Some(3)
+        );
+        assert_eq!(heartbeat.next_ping(start + Duration::from_secs(90)), None);
+    }
+
+    #[test]
+    fn test_welcome_negotiates_protocol_version() {
+        let settings = heartbeat_settings(&Config {
+            heartbeat_interval_secs: 0,
+            heartbeat_timeout_secs: 20,
+            ..Config::default()
+        });
+        match welcome(PROTOCOL_VERSION + 1, settings) {
+            ServerEvent::Welcome {
+                protocol_version,
+                capabilities,
+                heartbeat,
+                ..
+            } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert!(capabilities.contains(&Capability::Resume));
+                assert_eq!(
+                    heartbeat,
+                    HeartbeatSettings {
+                        interval_secs: 1,
+                        timeout_secs: 20
+                    }
+                );
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        for timeout_secs in [0, 10, 15] {
+            let settings = heartbeat_settings(&Config {
+                heartbeat_interval_secs: 15,
+                heartbeat_timeout_secs: timeout_secs,
+                ..Config::default()
+            });
+            assert_eq!(settings.timeout_secs, 30);
+        }
+
+        assert!(matches!(
+            welcome(MIN_PROTOCOL_VERSION - 1, settings),
+            ServerEvent::ProtocolError(ProtocolError {
+                kind: ProtocolErrorKind::UnsupportedVersion,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_input_submits_for_version_1_clients() {
+        let app_state = AppState::new(Config {
+            claude_path: "cat".to_string(),
+            ..Config::default()
+        });
+        let manager = &app_state.session_manager;
+        let session_id = manager
+            .create_session(SessionConfig {
+                name: "Test Session".to_string(),
+                directory: "/tmp".to_string(),
+                size: None,
+                initial_prompt: None,
+            })
+            .await
+            .unwrap();
+        let mut events = manager.subscribe(&session_id).await.unwrap();
+
+        // A client that never says hello speaks version 1
+        let (mut connection, _rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let input = |input: &str| ClientEvent::SendInput {
+            session_id: session_id.clone(),
+            input: input.to_string(),
+        };
+        handle_client_event(input("old\n"), &mut connection, &app_state)
+            .await
+            .unwrap();
+
+        // `cat` only repeats a line once it is submitted
+        let mut received = String::new();
+        while !received.contains("old\r\nold") {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("timed out waiting for the submitted line")
+                .unwrap();
+            if let SessionEvent::Output { data, .. } = event {
+                received.push_str(&String::from_utf8_lossy(&data));
+            }
+        }
+
+        let hello = ClientEvent::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![],
+        };
+        handle_client_event(hello, &mut connection, &app_state)
+            .await
+            .unwrap();
+        assert_eq!(connection.protocol_version, PROTOCOL_VERSION);
+
+        manager.close_session(&session_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_only_declared_capabilities_are_used() {
+        let app_state = AppState::new(Config::default());
+        let (mut connection, rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let binary = ClientEvent::SetOutputEncoding {
+            encoding: OutputEncoding::Binary,
+        };
+
+        handle_client_event(binary.clone(), &mut connection, &app_state)
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.pop().await,
+            Outgoing::SetEncoding(OutputEncoding::Text)
+        ));
+        assert!(!connection.supports(Capability::Heartbeat));
+
+        let hello = ClientEvent::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![Capability::BinaryOutput, Capability::Heartbeat],
+        };
+        handle_client_event(hello, &mut connection, &app_state)
+            .await
+            .unwrap();
+        rx.pop().await;
+        handle_client_event(binary, &mut connection, &app_state)
+            .await
+            .unwrap();
+        assert!(matches!(
+            rx.pop().await,
+            Outgoing::SetEncoding(OutputEncoding::Binary)
+        ));
+        assert!(connection.supports(Capability::Heartbeat));
+        assert!(!connection.supports(Capability::Resync));
+    }
+
+    #[test]
+    fn test_registry_changes_become_session_list_events() {
+        let info = crate::models::session::SessionInfo {
+            id: "s".into(),
+            name: "Test".into(),
+            directory: "/tmp".into(),
+            created_at: "2026-01-01T00:00:00Z".into(),
+            status: SessionStatus::Starting,
+        };
+
+        assert_eq!(
+            change_event(SessionChange::Created(info.clone())),
+            Some(ServerEvent::SessionAdded {
+                session: info.into(),
+            })
+        );
+        assert_eq!(
+            change_event(SessionChange::StatusChanged {
+                session_id: "s".into(),
+                status: SessionStatus::Exited,
+            }),
+            Some(ServerEvent::SessionStatusChanged {
+                session_id: "s".into(),
+                status: SessionStatus::Exited,
+            })
+        );
+        assert_eq!(
+            change_event(SessionChange::Closed {
+                session_id: "s".into(),
+            }),
+            Some(ServerEvent::SessionRemoved {
+                session_id: "s".into(),
+            })
+        );
+        assert_eq!(
+            change_event(SessionChange::Exited {
+                session_id: "s".into(),
+                reason: "Process exited: Success".into(),
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_text_encoding_joins_split_characters() {
+        let (mut connection, _rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let crab = "🦀".as_bytes();
+
+        assert!(connection
+            .encode(output("s", 2, &crab[..2]))
+            .unwrap()
+            .is_empty());
+        let messages = connection.encode(output("s", 4, &crab[2..])).unwrap();
+
+        assert_eq!(
+            decode_event(&messages[0]),
+            ServerEvent::TerminalOutput {
+                session_id: "s".into(),
+                data: "🦀".into(),
+                offset: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_text_offsets_stop_before_held_back_bytes() {
+        let (mut connection, _rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let crab = "🦀".as_bytes();
+
+        let messages = connection
+            .encode(output("s", 3, &[b'a', crab[0], crab[1]]))
+            .unwrap();
+        assert_eq!(
+            decode_event(&messages[0]),
+            ServerEvent::TerminalOutput {
+                session_id: "s".into(),
+                data: "a".into(),
+                offset: 1,
+            }
+        );
+
+        // A client resuming from offset 1 gets the whole crab again
+        connection
+            .encode(Outgoing::Restart {
+                session_id: "s".into(),
+            })
+            .unwrap();
+        let messages = connection.encode(output("s", 5, crab)).unwrap();
+        assert_eq!(
+            decode_event(&messages[0]),
+            ServerEvent::TerminalOutput {
+                session_id: "s".into(),
+                data: "🦀".into(),
+                offset: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn test_binary_encoding_hands_over_pending_bytes() {
+        let (mut connection, _rx) = ClientConnection::new(DEFAULT_CLIENT_QUEUE_BYTES);
+        let crab = "🦀".as_bytes();
+
+        connection.encode(output("s", 2, &crab[..2])).unwrap();
+        let messages = connection
+            .encode(Outgoing::SetEncoding(OutputEncoding::Binary))
+            .unwrap();
+        assert_eq!(
+            decode_event(&messages[0]),
+            ServerEvent::OutputEncodingChanged {
+                encoding: OutputEncoding::Binary,
+            }
+        );
+
+        let messages = [
+            &messages[1..],
+            &connection.encode(output("s", 4, &crab[2..])).unwrap()[..],
+        ]
+        .concat();
+        let frames: Vec<OutputFrame> = messages
+            .iter()
+            .map(|message| match message {
+                Message::Binary(bytes) => OutputFrame::decode(bytes).unwrap(),
+                other => panic!("unexpected message {:?}", other),
+            })
+            .collect();
+
+        let offsets: Vec<u64> = frames.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, [2, 4]);
+        let data: Vec<u8> = frames.into_iter().flat_map(|frame| frame.data).collect();
+        assert_eq!(data, crab);
+    }
+}
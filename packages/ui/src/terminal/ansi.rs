@@ -0,0 +1,573 @@
+//! Rendering of terminal output with its colors and styles
+
+use api::models::session::TerminalSize;
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+/// Longest control sequence kept before it is given up on
+const MAX_SEQUENCE_LEN: usize = 64;
+
+/// Columns between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Lines kept before the oldest are dropped
+pub const MAX_LINES: usize = 5000;
+
+/// Colors used for the default foreground and background when inverted,
+/// set on the output area by the stylesheet
+const DEFAULT_FOREGROUND: &str = "var(--terminal-fg)";
+const DEFAULT_BACKGROUND: &str = "var(--terminal-bg)";
+
+/// The 16 standard colors, normal then bright
+const PALETTE: [&str; 16] = [
+    "#000000", "#cd3131", "#0dbc79", "#e5e510", "#2472c8", "#bc3fbc", "#11a8cd", "#e5e5e5",
+    "#666666", "#f14c4c", "#23d18b", "#f5f543", "#3b8eea", "#d670d6", "#29b8db", "#ffffff",
+];
+
+/// Channel levels of the 6x6x6 color cube in the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// An entry of the 256 color palette, the first 16 being the standard
+    /// colors
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    pub fn css(&self) -> String {
+        match *self {
+            Color::Indexed(index @ 0..=15) => PALETTE[index as usize].to_string(),
+            Color::Indexed(index @ 16..=231) => {
+                let index = index - 16;
+                let level = |value: u8| CUBE_LEVELS[value as usize % 6];
+                Color::Rgb(level(index / 36), level(index / 6), level(index)).css()
+            }
+            Color::Indexed(index) => {
+                let gray = 8 + 10 * (index - 232);
+                Color::Rgb(gray, gray, gray).css()
+            }
+            Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+/// Graphic rendition of a character, as set by SGR sequences
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// Inline CSS for the style, empty for the default one
+    pub fn css(&self) -> String {
+        let mut foreground = self.foreground.map(|color| color.css());
+        let mut background = self.background.map(|color| color.css());
+        if self.inverse {
+            (foreground, background) = (
+                background.or_else(|| Some(DEFAULT_BACKGROUND.to_string())),
+                foreground.or_else(|| Some(DEFAULT_FOREGROUND.to_string())),
+            );
+        }
+
+        let mut css = String::new();
+        if let Some(color) = foreground {
+            css.push_str(&format!("color: {};", color));
+        }
+        if let Some(color) = background {
+            css.push_str(&format!("background-color: {};", color));
+        }
+        if self.bold {
+            css.push_str("font-weight: bold;");
+        }
+        if self.dim {
+            css.push_str("opacity: 0.6;");
+        }
+        if self.italic {
+            css.push_str("font-style: italic;");
+        }
+        if self.underline {
+            css.push_str("text-decoration: underline;");
+        }
+        css
+    }
+
+    /// Apply the parameters of an SGR sequence, `ESC [ ... m`
+    ///
+    /// Extended colors are accepted in both the `38;5;n` form and the
+    /// colon separated `38:5:n` one.
+    fn apply(&mut self, params: &str) {
+        let mut params = params.split(';');
+        while let Some(param) = params.next() {
+            if param.contains(':') {
+                self.apply_subparams(param);
+                continue;
+            }
+
+            match param.parse::<u16>().unwrap_or(0) {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 | 21 => self.underline = true,
+                7 => self.inverse = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                code @ 30..=37 => self.foreground = Some(Color::Indexed(code as u8 - 30)),
+                38 => self.foreground = extended_color(&mut params).or(self.foreground),
+                39 => self.foreground = None,
+                code @ 40..=47 => self.background = Some(Color::Indexed(code as u8 - 40)),
+                48 => self.background = extended_color(&mut params).or(self.background),
+                49 => self.background = None,
+                code @ 90..=97 => self.foreground = Some(Color::Indexed(code as u8 - 90 + 8)),
+                code @ 100..=107 => self.background = Some(Color::Indexed(code as u8 - 100 + 8)),
+                _ => {}
+            }
+        }
+    }
+
+    /// A parameter with colon separated parts, as in `38:2::r:g:b` or the
+    /// underline styles `4:0` to `4:5`
+    fn apply_subparams(&mut self, param: &str) {
+        let mut parts = param.split(':');
+        match parts.next() {
+            Some("4") => self.underline = parts.next() != Some("0"),
+            Some(code @ ("38" | "48")) => {
+                // A color space id may come before the components, so for
+                // true color the last three are taken
+                let color = match parts.collect::<Vec<_>>().as_slice() {
+                    ["2", .., r, g, b] => rgb(r, g, b),
+                    rest => extended_color(&mut rest.iter().copied()),
+                };
+                if code == "38" {
+                    self.foreground = color.or(self.foreground);
+                } else {
+                    self.background = color.or(self.background);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The color after `38` or `48`: `5;n` for the palette or `2;r;g;b`
+fn extended_color<'a>(params: &mut impl Iterator<Item = &'a str>) -> Option<Color> {
+    match params.next()? {
+        "5" => Some(Color::Indexed(params.next()?.parse().ok()?)),
+        "2" => rgb(params.next()?, params.next()?, params.next()?),
+        _ => None,
+    }
+}
+
+fn rgb(r: &str, g: &str, b: &str) -> Option<Color> {
+    Some(Color::Rgb(
+        r.parse().ok()?,
+        g.parse().ok()?,
+        b.parse().ok()?,
+    ))
+}
+
+/// A run of text in one style
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// One line of output
+///
+/// Its spans are worked out when first asked for and kept until the line
+/// changes, so redrawing one line leaves the others as they were.
+#[derive(Debug, Clone, Default)]
+pub struct Line {
+    cells: Vec<(char, Style)>,
+    spans: OnceCell<Vec<Span>>,
+}
+
+impl Line {
+    /// The line split into runs of one style
+    pub fn spans(&self) -> &[Span] {
+        self.spans.get_or_init(|| {
+            let mut spans: Vec<Span> = Vec::new();
+            for &(c, style) in &self.cells {
+                match spans.last_mut() {
+                    Some(span) if span.style == style => span.text.push(c),
+                    _ => spans.push(Span {
+                        text: c.to_string(),
+                        style,
+                    }),
+                }
+            }
+            spans
+        })
+    }
+
+    /// The cells, for a change that outdates the spans
+    fn cells_mut(&mut self) -> &mut Vec<(char, Style)> {
+        self.spans.take();
+        &mut self.cells
+    }
+}
+
+impl PartialEq for Line {
+    fn eq(&self, other: &Self) -> bool {
+        self.cells == other.cells
+    }
+}
+
+impl Eq for Line {}
+
+/// Where the parser is within a control sequence
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+enum Sequence {
+    #[default]
+    None,
+    /// After ESC
+    Escape,
+    /// Within `ESC [`, with the parameters so far
+    Csi(String),
+    /// Within a string whose payload is not shown, until BEL or `ESC \`:
+    /// operating system commands (`ESC ]`), device control strings
+    /// (`ESC P`), and the rarely used `ESC X`, `ESC ^` and `ESC _`
+    Text,
+    TextEscape,
+    /// Character set selection, `ESC (` and the like, takes one more byte
+    Charset,
+}
+
+/// Lays terminal output out into lines of styled text
+///
+/// Output is fed as it arrives; sequences split across chunks are picked
+/// up where they left off. Carriage returns, backspaces, line clears and
+/// relative cursor movement overwrite earlier text the way a terminal
+/// does, so spinners and progress lines redraw in place. Sequences that
+/// need a fixed screen, such as absolute cursor positioning, are ignored.
+/// Only the last `MAX_LINES` lines are kept.
+///
+/// Lines are shared between clones and copied only when changed, so a
+/// clone costs a pointer per line and unchanged lines compare equal
+/// without looking at their cells.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnsiRenderer {
+    lines: Vec<Rc<Line>>,
+    row: usize,
+    col: usize,
+    style: Style,
+    sequence: Sequence,
+}
+
+impl AnsiRenderer {
+    pub fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            match std::mem::take(&mut self.sequence) {
+                Sequence::None => self.print(c),
+                Sequence::Escape => {
+                    self.sequence = match c {
+                        '[' => Sequence::Csi(String::new()),
+                        ']' | 'P' | 'X' | '^' | '_' => Sequence::Text,
+                        '(' | ')' | '*' | '+' => Sequence::Charset,
+                        // Other escapes, like saving the cursor, change nothing shown
+                        _ => Sequence::None,
+                    }
+                }
+                Sequence::Csi(mut params) => {
+                    if c == '\x1b' {
+                        // Abandoned for a new sequence
+                        self.sequence = Sequence::Escape;
+                    } else if ('\x40'..='\x7e').contains(&c) {
+                        self.control_sequence(&params, c);
+                    } else if params.len() < MAX_SEQUENCE_LEN {
+                        params.push(c);
+                        self.sequence = Sequence::Csi(params);
+                    }
+                }
+                Sequence::Text => {
+                    self.sequence = match c {
+                        '\x07' => Sequence::None,
+                        '\x1b' => Sequence::TextEscape,
+                        _ => Sequence::Text,
+                    }
+                }
+                Sequence::TextEscape | Sequence::Charset => {}
+            }
+        }
+    }
+
+    /// Whether no output has been fed yet
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The lines laid out so far
+    pub fn lines(&self) -> &[Rc<Line>] {
+        &self.lines
+    }
+
+    fn print(&mut self, c: char) {
+        match c {
+            '\x1b' => self.sequence = Sequence::Escape,
+            '\r' => self.col = 0,
+            '\n' => self.move_down(1),
+            '\x08' => self.col = self.col.saturating_sub(1),
+            '\t' => self.col = (self.col / TAB_WIDTH + 1) * TAB_WIDTH,
+            c if c.is_control() => {}
+            c => {
+                let (col, style) = (self.col, self.style);
+                let line = self.line();
+                if col < line.len() {
+                    line[col] = (c, style);
+                } else {
+                    line.resize(col, (' ', Style::default()));
+                    line.push((c, style));
+                }
+                self.col += 1;
+            }
+        }
+    }
+
+    /// The line under the cursor, created if output has not reached it yet
+    fn line(&mut self) -> &mut Vec<(char, Style)> {
+        if self.lines.len() <= self.row {
+            self.lines.resize_with(self.row + 1, Rc::default);
+        }
+        Rc::make_mut(&mut self.lines[self.row]).cells_mut()
+    }
+
+    fn move_down(&mut self, count: usize) {
+        self.row += count;
+        self.line();
+
+        let excess = self.lines.len().saturating_sub(MAX_LINES);
+        if excess > 0 {
+            self.lines.drain(..excess);
+            self.row -= excess;
+        }
+    }
+
+    /// Handle `ESC [ <params> <action>`
+    fn control_sequence(&mut self, params: &str, action: char) {
+        // Private modes, like hiding the cursor, change nothing shown
+        if params.starts_with(['<', '=', '>', '?']) {
+            return;
+        }
+
+        let first = params
+            .split(';')
+            .next()
+            .and_then(|param| param.parse::<usize>().ok())
+            .unwrap_or(0);
+        // Bounded, so a bogus count cannot make the lines grow without end
+        let count = first.clamp(1, TerminalSize::MAX_DIMENSION as usize);
+
+        match action {
+            'm' => self.style.apply(params),
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.move_down(count),
+            'C' => self.col = (self.col + count).min(TerminalSize::MAX_DIMENSION as usize),
+            'D' => self.col = self.col.saturating_sub(count),
+            'E' => {
+                self.move_down(count);
+                self.col = 0;
+            }
+            'F' => {
+                self.row = self.row.saturating_sub(count);
+                self.col = 0;
+            }
+            'G' => self.col = count - 1,
+            'K' => self.erase_in_line(first),
+            'J' => self.erase_in_display(first),
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: usize) {
+        let col = self.col;
+        let line = self.line();
+        match mode {
+            0 => line.truncate(col),
+            1 => {
+                let end = (col + 1).min(line.len());
+                line[..end].fill((' ', Style::default()));
+            }
+            _ => line.clear(),
+        }
+    }
+
+    /// Erase below the cursor, or everything: without a fixed screen,
+    /// clearing it clears all output so far
+    fn erase_in_display(&mut self, mode: usize) {
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                self.lines.truncate(self.row + 1);
+            }
+            1 => {
+                self.erase_in_line(1);
+                for line in &mut self.lines[..self.row] {
+                    Rc::make_mut(line).cells_mut().clear();
+                }
+            }
+            _ => {
+                self.lines.clear();
+                self.row = 0;
+                self.col = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(chunks: &[&str]) -> AnsiRenderer {
+        let mut renderer = AnsiRenderer::default();
+        for chunk in chunks {
+            renderer.feed(chunk);
+        }
+        renderer
+    }
+
+    fn text(renderer: &AnsiRenderer) -> Vec<String> {
+        renderer
+            .lines()
+            .iter()
+            .map(|line| line.spans().iter().map(|span| span.text.as_str()).collect())
+            .collect()
+    }
+
+    /// Style of the first character
+    fn style(output: &str) -> Style {
+        rendered(&[output]).lines()[0].spans()[0].style
+    }
+
+    #[test]
+    fn test_standard_and_extended_colors() {
+        assert_eq!(style("\x1b[31mx").foreground, Some(Color::Indexed(1)));
+        assert_eq!(style("\x1b[1;91mx").foreground, Some(Color::Indexed(9)));
+        assert_eq!(style("\x1b[104mx").background, Some(Color::Indexed(12)));
+
+        for output in ["\x1b[38;5;196mx", "\x1b[38:5:196mx"] {
+            let color = style(output).foreground.unwrap();
+            assert_eq!(color, Color::Indexed(196));
+            assert_eq!(color.css(), "#ff0000");
+        }
+        for output in [
+            "\x1b[48;2;1;2;3mx",
+            "\x1b[48:2:1:2:3mx",
+            "\x1b[48:2::1:2:3mx",
+        ] {
+            assert_eq!(style(output).background, Some(Color::Rgb(1, 2, 3)));
+        }
+        assert_eq!(Color::Indexed(232).css(), "#080808");
+
+        assert_eq!(style("\x1b[1;31m\x1b[0mx"), Style::default());
+        assert_eq!(style("\x1b[31;39mx").foreground, None);
+    }
+
+    #[test]
+    fn test_inverse_swaps_default_colors() {
+        assert_eq!(
+            style("\x1b[7mx").css(),
+            "color: var(--terminal-bg);background-color: var(--terminal-fg);"
+        );
+        assert_eq!(
+            style("\x1b[7;31mx").css(),
+            "color: var(--terminal-bg);background-color: #cd3131;"
+        );
+    }
+
+    #[test]
+    fn test_lines_are_redrawn_in_place() {
+        assert_eq!(text(&rendered(&["hello\rJ"])), ["Jello"]);
+        assert_eq!(text(&rendered(&["hello\r\x1b[Kbye"])), ["bye"]);
+        assert_eq!(text(&rendered(&["hello\x1b[3D\x1b[K"])), ["he"]);
+        assert_eq!(text(&rendered(&["hello\x1b[3D\x1b[1K"])), ["   lo"]);
+        assert_eq!(text(&rendered(&["hello\x1b[2Kx"])), ["     x"]);
+        assert_eq!(
+            text(&rendered(&["50%\r\n\x1b[1A\x1b[2K100%"])),
+            ["100%", ""]
+        );
+    }
+
+    #[test]
+    fn test_sequences_split_across_chunks() {
+        let renderer = rendered(&["plain \x1b[3", "1mred\x1b", "[0m"]);
+        let spans = renderer.lines()[0].spans();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[1].text, "red");
+        assert_eq!(spans[1].style.foreground, Some(Color::Indexed(1)));
+    }
+
+    #[test]
+    fn test_string_sequences_are_not_shown() {
+        assert_eq!(text(&rendered(&["a\x1b]0;title\x07b"])), ["ab"]);
+        assert_eq!(text(&rendered(&["a\x1bP1$r0m\x1b", "\\b"])), ["ab"]);
+        assert_eq!(
+            text(&rendered(&["a\x1b_apc\x1b\\b\x1b^pm\x1b\\c"])),
+            ["abc"]
+        );
+    }
+
+    #[test]
+    fn test_escape_abandons_control_sequence() {
+        let renderer = rendered(&["a\x1b[3\x1b[31mred"]);
+        assert_eq!(text(&renderer), ["ared"]);
+        assert_eq!(
+            renderer.lines()[0].spans()[1].style.foreground,
+            Some(Color::Indexed(1))
+        );
+    }
+
+    #[test]
+    fn test_clones_share_unchanged_lines() {
+        let before = rendered(&["one\r\ntwo\r\n50%"]);
+        let mut after = before.clone();
+        after.feed("\r100%");
+
+        assert!(Rc::ptr_eq(&before.lines()[0], &after.lines()[0]));
+        assert!(Rc::ptr_eq(&before.lines()[1], &after.lines()[1]));
+        assert_eq!(text(&before)[2], "50%");
+        assert_eq!(text(&after)[2], "100%");
+    }
+
+    #[test]
+    fn test_erase_in_display() {
+        let output = "one\r\ntwo\r\nthree";
+        assert_eq!(
+            text(&rendered(&[output, "\x1b[1A\x1b[2G\x1b[J"])),
+            ["one", "t"]
+        );
+        assert_eq!(
+            text(&rendered(&[output, "\x1b[1A\x1b[1J"])),
+            ["", "   ", "three"]
+        );
+
+        let cleared = rendered(&[output, "\x1b[2J"]);
+        assert!(cleared.is_empty());
+        assert_eq!(text(&rendered(&[output, "\x1b[2Jnew"])), ["new"]);
+    }
+
+    #[test]
+    fn test_output_stays_bounded() {
+        let far = rendered(&["\x1b[99999Cx"]);
+        assert_eq!(
+            text(&far)[0].chars().count(),
+            TerminalSize::MAX_DIMENSION as usize + 1
+        );
+
+        let long = rendered(&[&"line\r\n".repeat(MAX_LINES + 10), "last"]);
+        let lines = text(&long);
+        assert_eq!(lines.len(), MAX_LINES);
+        assert_eq!(lines.last().unwrap(), "last");
+    }
+}
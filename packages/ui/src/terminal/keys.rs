@@ -0,0 +1,230 @@
+//! Translation of browser key presses into terminal input
+
+use dioxus::prelude::{Key, Modifiers};
+
+/// The bytes a terminal sends for a key press, or `None` for keys that
+/// produce nothing (lone modifiers, shortcuts held with Meta) and for the
+/// clipboard shortcuts the browser should handle
+///
+/// Follows xterm: control characters for Ctrl combinations, an Escape
+/// prefix for Alt, and CSI sequences with a modifier parameter for
+/// navigation keys.
+pub fn key_sequence(key: &Key, modifiers: Modifiers) -> Option<String> {
+    // Leave Cmd/Win shortcuts, like copying, to the browser
+    if modifiers.meta() || is_clipboard_shortcut(key, modifiers) {
+        return None;
+    }
+
+    let sequence = match key {
+        // Browsers report AltGr as Ctrl+Alt, and the character it typed is
+        // meant as is
+        Key::Character(text) if is_alt_gr(text, modifiers) => return Some(text.clone()),
+        Key::Character(text) => {
+            let text = if modifiers.ctrl() {
+                control_character(text)?.to_string()
+            } else {
+                text.clone()
+            };
+            return Some(alt_prefixed(text, modifiers));
+        }
+        Key::Enter => return Some(alt_prefixed("\r".into(), modifiers)),
+        Key::Backspace if modifiers.ctrl() => return Some("\x08".into()),
+        Key::Backspace => return Some(alt_prefixed("\x7f".into(), modifiers)),
+        Key::Escape => return Some("\x1b".into()),
+        Key::Tab if modifiers.shift() => return Some("\x1b[Z".into()),
+        Key::Tab => return Some("\t".into()),
+
+        Key::ArrowUp => Csi::Final('A'),
+        Key::ArrowDown => Csi::Final('B'),
+        Key::ArrowRight => Csi::Final('C'),
+        Key::ArrowLeft => Csi::Final('D'),
+        Key::Home => Csi::Final('H'),
+        Key::End => Csi::Final('F'),
+        Key::Insert => Csi::Tilde(2),
+        Key::Delete => Csi::Tilde(3),
+        Key::PageUp => Csi::Tilde(5),
+        Key::PageDown => Csi::Tilde(6),
+        Key::F1 => Csi::Ss3('P'),
+        Key::F2 => Csi::Ss3('Q'),
+        Key::F3 => Csi::Ss3('R'),
+        Key::F4 => Csi::Ss3('S'),
+        Key::F5 => Csi::Tilde(15),
+        Key::F6 => Csi::Tilde(17),
+        Key::F7 => Csi::Tilde(18),
+        Key::F8 => Csi::Tilde(19),
+        Key::F9 => Csi::Tilde(20),
+        Key::F10 => Csi::Tilde(21),
+        Key::F11 => Csi::Tilde(23),
+        Key::F12 => Csi::Tilde(24),
+        _ => return None,
+    };
+
+    Some(sequence.encode(modifier_parameter(modifiers)))
+}
+
+/// Escape sequences for keys without a character of their own
+enum Csi {
+    /// `ESC [ <final>`, or `ESC [ 1 ; <modifiers> <final>`
+    Final(char),
+    /// `ESC [ <code> ~`, or `ESC [ <code> ; <modifiers> ~`
+    Tilde(u8),
+    /// `ESC O <final>`, switching to the CSI form when modified
+    Ss3(char),
+}
+
+impl Csi {
+    fn encode(&self, modifiers: Option<u8>) -> String {
+        match (self, modifiers) {
+            (Csi::Final(c), None) => format!("\x1b[{}", c),
+            (Csi::Final(c) | Csi::Ss3(c), Some(m)) => format!("\x1b[1;{}{}", m, c),
+            (Csi::Tilde(code), None) => format!("\x1b[{}~", code),
+            (Csi::Tilde(code), Some(m)) => format!("\x1b[{};{}~", code, m),
+            (Csi::Ss3(c), None) => format!("\x1bO{}", c),
+        }
+    }
+}
+
+/// xterm's modifier parameter: 1 plus a bit each for Shift, Alt and Ctrl
+fn modifier_parameter(modifiers: Modifiers) -> Option<u8> {
+    let bits = u8::from(modifiers.shift())
+        | u8::from(modifiers.alt()) << 1
+        | u8::from(modifiers.ctrl()) << 2;
+    (bits != 0).then_some(bits + 1)
+}
+
+/// Whether the key is one of the copy and paste shortcuts of Linux and
+/// Windows terminals: Ctrl+Shift+C/V, Ctrl+V and Shift+Insert
+///
+/// Ctrl+V costs the terminal its literal-next character, but the browser
+/// only fires a paste if the key press is left alone.
+fn is_clipboard_shortcut(key: &Key, modifiers: Modifiers) -> bool {
+    if modifiers.alt() {
+        return false;
+    }
+    match key {
+        Key::Character(text) if modifiers.ctrl() => {
+            let shift = modifiers.shift();
+            matches!(text.as_str(), "v" | "V") || (shift && matches!(text.as_str(), "c" | "C"))
+        }
+        Key::Insert => modifiers.shift() && !modifiers.ctrl(),
+        _ => false,
+    }
+}
+
+/// Whether Ctrl+Alt typed a character other than a letter, which is how
+/// AltGr layouts produce characters like `@` or `{`
+fn is_alt_gr(text: &str, modifiers: Modifiers) -> bool {
+    modifiers.ctrl()
+        && modifiers.alt()
+        && !text.is_empty()
+        && !text
+            .chars()
+            .any(|c| c.is_control() || c.is_ascii_alphabetic())
+}
+
+/// The control character for Ctrl held with a key, as in Ctrl-C for ETX
+fn control_character(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    let (c, None) = (chars.next()?, chars.next()) else {
+        return None;
+    };
+
+    let code = match c.to_ascii_uppercase() {
+        c @ '@'..='_' => c as u8 - b'@',
+        ' ' | '2' => 0,
+        '3' => 0x1b,
+        '4' => 0x1c,
+        '5' => 0x1d,
+        '6' => 0x1e,
+        '7' | '/' => 0x1f,
+        '8' | '?' => 0x7f,
+        _ => return None,
+    };
+    Some(code as char)
+}
+
+/// Alt sends the key prefixed with Escape, as terminals set to "meta sends
+/// escape" do
+fn alt_prefixed(text: String, modifiers: Modifiers) -> String {
+    if modifiers.alt() {
+        format!("\x1b{}", text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn character(text: &str) -> Key {
+        Key::Character(text.to_string())
+    }
+
+    #[test]
+    fn test_ctrl_and_alt_characters() {
+        assert_eq!(
+            key_sequence(&character("c"), Modifiers::CONTROL),
+            Some("\x03".into())
+        );
+        assert_eq!(
+            key_sequence(&character("x"), Modifiers::ALT),
+            Some("\x1bx".into())
+        );
+        assert_eq!(
+            key_sequence(&character("c"), Modifiers::CONTROL | Modifiers::ALT),
+            Some("\x1b\x03".into())
+        );
+        assert_eq!(key_sequence(&character("c"), Modifiers::META), None);
+    }
+
+    #[test]
+    fn test_clipboard_shortcuts_are_left_to_the_browser() {
+        let ctrl_shift = Modifiers::CONTROL | Modifiers::SHIFT;
+        assert_eq!(key_sequence(&character("C"), ctrl_shift), None);
+        assert_eq!(key_sequence(&character("V"), ctrl_shift), None);
+        assert_eq!(key_sequence(&character("v"), Modifiers::CONTROL), None);
+        assert_eq!(key_sequence(&Key::Insert, Modifiers::SHIFT), None);
+        // Ctrl+C on its own still interrupts
+        assert_eq!(
+            key_sequence(&character("c"), Modifiers::CONTROL),
+            Some("\x03".into())
+        );
+    }
+
+    #[test]
+    fn test_alt_gr_characters_are_sent_as_typed() {
+        let alt_gr = Modifiers::CONTROL | Modifiers::ALT;
+        assert_eq!(key_sequence(&character("@"), alt_gr), Some("@".into()));
+        assert_eq!(key_sequence(&character("€"), alt_gr), Some("€".into()));
+        assert_eq!(key_sequence(&character("{"), alt_gr), Some("{".into()));
+    }
+
+    #[test]
+    fn test_navigation_and_function_keys() {
+        assert_eq!(
+            key_sequence(&Key::Tab, Modifiers::SHIFT),
+            Some("\x1b[Z".into())
+        );
+        assert_eq!(
+            key_sequence(&Key::ArrowUp, Modifiers::empty()),
+            Some("\x1b[A".into())
+        );
+        assert_eq!(
+            key_sequence(&Key::ArrowUp, Modifiers::CONTROL),
+            Some("\x1b[1;5A".into())
+        );
+        assert_eq!(
+            key_sequence(&Key::PageDown, Modifiers::SHIFT),
+            Some("\x1b[6;2~".into())
+        );
+        assert_eq!(
+            key_sequence(&Key::F1, Modifiers::empty()),
+            Some("\x1bOP".into())
+        );
+        assert_eq!(
+            key_sequence(&Key::F1, Modifiers::SHIFT),
+            Some("\x1b[1;2P".into())
+        );
+    }
+}
@@ -1,5 +1,7 @@
 //! Terminal UI components
 
+pub mod ansi;
+pub mod keys;
 pub mod session_list;
 pub mod session_manager;
 pub mod terminal_view;
@@ -1,4 +1,5 @@
 use api::models::message::SessionInfo;
+use api::models::session::{SessionSignal, SessionStatus};
 use dioxus::prelude::*;
 
 #[derive(Props, Clone, PartialEq)]
@@ -7,6 +8,8 @@ pub struct SessionListProps {
     pub active_session_id: Option<String>,
     pub on_select: EventHandler<String>,
     pub on_close: EventHandler<String>,
+    /// Called to pause or resume a session's foreground job
+    pub on_signal: EventHandler<(String, SessionSignal)>,
 }
 
 #[component]
@@ -33,6 +36,7 @@ pub fn SessionList(props: SessionListProps) -> Element {
                             is_active: props.active_session_id.as_ref() == Some(&session.id),
                             on_select: move |id| props.on_select.call(id),
                             on_close: move |id| props.on_close.call(id),
+                            on_signal: move |signal| props.on_signal.call(signal),
                         }
                     }
                 }
@@ -47,12 +51,21 @@ struct SessionItemProps {
     is_active: bool,
     on_select: EventHandler<String>,
     on_close: EventHandler<String>,
+    on_signal: EventHandler<(String, SessionSignal)>,
 }
 
 #[component]
 fn SessionItem(props: SessionItemProps) -> Element {
     let session_id = props.session.id.clone();
     let session_id_for_close = session_id.clone();
+    let session_id_for_signal = session_id.clone();
+
+    // Paused sessions can be resumed, live ones paused, exited ones neither
+    let job_control = match props.session.status {
+        SessionStatus::Paused => Some((SessionSignal::Resume, "▶", "Resume")),
+        SessionStatus::Exited => None,
+        _ => Some((SessionSignal::Suspend, "⏸", "Pause")),
+    };
 
     let item_class = if props.is_active {
         "session-item active"
@@ -67,11 +80,30 @@ fn SessionItem(props: SessionItemProps) -> Element {
 
             div {
                 class: "session-info",
-                div { class: "session-name", "{props.session.name}" }
+                div {
+                    class: "session-header",
+                    span { class: "session-name", "{props.session.name}" }
+                    span {
+                        class: "session-status {status_class(props.session.status)}",
+                        "{props.session.status.label()}"
+                    }
+                }
                 div { class: "session-directory", "{props.session.directory}" }
                 div { class: "session-time", "{format_time(&props.session.created_at)}" }
             }
 
+            if let Some((signal, icon, title)) = job_control {
+                button {
+                    class: "btn-job-control",
+                    title: "{title}",
+                    onclick: move |evt| {
+                        evt.stop_propagation();
+                        props.on_signal.call((session_id_for_signal.clone(), signal));
+                    },
+                    "{icon}"
+                }
+            }
+
             button {
                 class: "btn-close",
                 onclick: move |evt| {
@@ -84,6 +116,17 @@ fn SessionItem(props: SessionItemProps) -> Element {
     }
 }
 
+fn status_class(status: SessionStatus) -> &'static str {
+    match status {
+        SessionStatus::Starting => "status-starting",
+        SessionStatus::Running => "status-running",
+        SessionStatus::Idle => "status-idle",
+        SessionStatus::WaitingForInput => "status-waiting",
+        SessionStatus::Paused => "status-paused",
+        SessionStatus::Exited => "status-exited",
+    }
+}
+
 fn format_time(iso_time: &str) -> String {
     if let Some(time_part) = iso_time.split('T').nth(1) {
         if let Some(time) = time_part.split('.').next() {
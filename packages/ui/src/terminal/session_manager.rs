@@ -8,8 +8,8 @@ pub struct SessionManagerProps {
 
 #[component]
 pub fn SessionManager(props: SessionManagerProps) -> Element {
-    let mut task_description = use_signal(|| String::new());
-    let mut selected_directory = use_signal(|| String::new());
+    let mut task_description = use_signal(String::new);
+    let mut selected_directory = use_signal(String::new);
     let mut error_message = use_signal(|| Option::<String>::None);
 
     // Reactively update selected directory when directories become available or change
@@ -36,11 +36,12 @@ pub fn SessionManager(props: SessionManagerProps) -> Element {
 
         error_message.set(None);
 
-        // Generate a session name from task (first 50 chars)
-        let session_name = if task.len() > 50 {
-            format!("{}...", &task[..50])
+        // Generate a session name from the task's first line (first 50 chars)
+        let first_line = task.lines().next().unwrap_or_default();
+        let session_name = if first_line.chars().count() > 50 {
+            format!("{}...", first_line.chars().take(50).collect::<String>())
         } else {
-            task.clone()
+            first_line.to_string()
         };
 
         // Pass name, directory, and the full command/task
@@ -50,7 +51,6 @@ pub fn SessionManager(props: SessionManagerProps) -> Element {
         task_description.set(String::new());
     };
 
-    let mut handle_create = move |_| create_session();
     let handle_button_click = move |_| create_session();
 
     rsx! {
@@ -63,15 +63,17 @@ pub fn SessionManager(props: SessionManagerProps) -> Element {
             // Full-width task description input (headless - no label)
             div { class: "form-group-headless",
                 div { class: "input-container",
-                    input {
-                        r#type: "text",
+                    textarea {
                         class: "task-input",
+                        rows: "{task_description.read().lines().count().clamp(1, 8)}",
                         value: "{task_description}",
                         placeholder: "Find a small pending task in the code and do it",
                         oninput: move |evt| task_description.set(evt.value().clone()),
+                        // Shift+Enter starts a new line of the task
                         onkeydown: move |evt| {
-                            if evt.key() == Key::Enter {
-                                handle_create(evt);
+                            if evt.key() == Key::Enter && !evt.modifiers().shift() {
+                                evt.prevent_default();
+                                create_session();
                             }
                         },
                     }
@@ -1,4 +1,8 @@
+use super::ansi::{AnsiRenderer, Line};
+use super::keys::key_sequence;
+use api::models::session::TerminalSize;
 use dioxus::prelude::*;
+use std::rc::Rc;
 
 #[cfg(target_family = "wasm")]
 use web_sys;
@@ -6,13 +10,43 @@ use web_sys;
 #[derive(Props, Clone, PartialEq)]
 pub struct TerminalViewProps {
     pub session_id: String,
-    pub output: Vec<String>,
+    /// The session's output, laid out as it arrived
+    pub output: Rc<AnsiRenderer>,
+    /// Called with text to type into the session, delivered as one paste
     pub on_send_input: EventHandler<String>,
+    /// Called in raw mode with the bytes for each key press
+    pub on_send_keys: EventHandler<String>,
+    /// Called with the visible character grid on mount and on every resize
+    pub on_resize: EventHandler<TerminalSize>,
 }
 
+/// Characters in the hidden ruler used to measure one cell
+const RULER_LEN: usize = 10;
+
+/// Most lines the composer shows before it scrolls
+const MAX_INPUT_ROWS: usize = 8;
+
 #[component]
 pub fn TerminalView(props: TerminalViewProps) -> Element {
-    let mut input_value = use_signal(|| String::new());
+    let mut input_value = use_signal(String::new);
+    // Whether key presses go straight to the terminal instead of the line
+    let mut raw_mode = use_signal(|| false);
+    let mut cell_size = use_signal(|| None::<(f64, f64)>);
+    let mut output_size = use_signal(|| None::<(f64, f64)>);
+    let mut reported_size = use_signal(|| None::<TerminalSize>);
+
+    // Report the character grid whenever the output area or font metrics change
+    use_effect(move || {
+        let (Some(cell), Some(area)) = (cell_size(), output_size()) else {
+            return;
+        };
+        if let Some(size) = grid_size(cell, area) {
+            if reported_size.peek().as_ref() != Some(&size) {
+                reported_size.set(Some(size));
+                props.on_resize.call(size);
+            }
+        }
+    });
 
     #[cfg(target_family = "wasm")]
     let mut output_ref = use_signal(|| None::<web_sys::Element>);
@@ -25,22 +59,18 @@ pub fn TerminalView(props: TerminalViewProps) -> Element {
         }
     });
 
-    let handle_send_click = move |_evt: dioxus::prelude::Event<dioxus::prelude::MouseData>| {
+    // Type the composed text, then press Enter to submit all of it at once
+    let mut submit = move || {
         let input = input_value.read().clone();
         if !input.is_empty() {
             props.on_send_input.call(input);
+            props.on_send_keys.call("\r".to_string());
             input_value.set(String::new());
         }
     };
 
-    let mut handle_send_keydown =
-        move |_evt: dioxus::prelude::Event<dioxus::prelude::KeyboardData>| {
-            let input = input_value.read().clone();
-            if !input.is_empty() {
-                props.on_send_input.call(input);
-                input_value.set(String::new());
-            }
-        };
+    // Grow with the composed text, up to a point
+    let input_rows = input_value.read().lines().count().clamp(1, MAX_INPUT_ROWS);
 
     rsx! {
         div {
@@ -54,12 +84,28 @@ pub fn TerminalView(props: TerminalViewProps) -> Element {
                         output_ref.set(Some(element.clone()));
                     }
                 },
+                onresize: move |evt| {
+                    if let Ok(size) = evt.get_content_box_size() {
+                        output_size.set(Some((size.width, size.height)));
+                    }
+                },
+
+                // Invisible run of characters in the output font, measured once
+                span {
+                    class: "terminal-ruler",
+                    aria_hidden: "true",
+                    onmounted: move |evt| async move {
+                        if let Ok(rect) = evt.data().get_client_rect().await {
+                            cell_size.set(Some((rect.width() / RULER_LEN as f64, rect.height())));
+                        }
+                    },
+                    {"M".repeat(RULER_LEN)}
+                }
 
-                for (idx, line) in props.output.iter().enumerate() {
-                    div {
+                for (idx, line) in props.output.lines().iter().enumerate() {
+                    OutputLine {
                         key: "{props.session_id}-{idx}",
-                        class: "output-line",
-                        dangerous_inner_html: "{escape_html(line)}"
+                        line: line.clone(),
                     }
                 }
 
@@ -71,34 +117,83 @@ pub fn TerminalView(props: TerminalViewProps) -> Element {
             div {
                 class: "terminal-input-area",
 
-                input {
-                    r#type: "text",
-                    class: "terminal-input",
+                textarea {
+                    rows: "{input_rows}",
+                    class: if raw_mode() { "terminal-input raw" } else { "terminal-input" },
                     value: "{input_value}",
-                    placeholder: "Type a command and press Enter...",
-                    oninput: move |evt| input_value.set(evt.value().clone()),
+                    placeholder: if raw_mode() {
+                        "Raw mode: keys go straight to the terminal"
+                    } else {
+                        "Type a command and press Enter, Shift+Enter for a new line..."
+                    },
+                    oninput: move |evt| {
+                        if raw_mode() {
+                            // Only pastes, including the clipboard shortcuts key_sequence
+                            // leaves alone, and composed text get this far
+                            props.on_send_input.call(evt.value());
+                            input_value.set(String::new());
+                        } else {
+                            input_value.set(evt.value().clone());
+                        }
+                    },
                     onkeydown: move |evt| {
-                        if evt.key() == Key::Enter {
-                            handle_send_keydown(evt);
+                        if raw_mode() {
+                            if let Some(keys) = key_sequence(&evt.key(), evt.modifiers()) {
+                                evt.prevent_default();
+                                props.on_send_keys.call(keys);
+                            }
+                        } else if evt.key() == Key::Enter && !evt.modifiers().shift() {
+                            evt.prevent_default();
+                            submit();
                         }
                     },
                     autofocus: true,
                 }
 
                 button {
-                    class: "btn btn-send",
-                    onclick: handle_send_click,
-                    "Send"
+                    class: if raw_mode() { "btn btn-raw active" } else { "btn btn-raw" },
+                    title: "Send every key press, including Ctrl-C, Esc, Tab and arrows",
+                    onclick: move |_| raw_mode.toggle(),
+                    "Raw keys"
+                }
+
+                if !raw_mode() {
+                    button {
+                        class: "btn btn-send",
+                        onclick: move |_| submit(),
+                        "Send"
+                    }
                 }
             }
         }
     }
 }
 
-fn escape_html(text: &str) -> String {
-    text.replace('&', "&amp;")
-        .replace('<', "&lt;")
-        .replace('>', "&gt;")
-        .replace('"', "&quot;")
-        .replace('\'', "&#x27;")
+/// One line of output, only rendered again when the line itself changed
+#[component]
+fn OutputLine(line: Rc<Line>) -> Element {
+    rsx! {
+        div {
+            class: "output-line",
+            for segment in line.spans() {
+                span { style: "{segment.style.css()}", "{segment.text}" }
+            }
+        }
+    }
+}
+
+/// Number of whole character cells that fit in the output area
+fn grid_size(
+    (cell_width, cell_height): (f64, f64),
+    (width, height): (f64, f64),
+) -> Option<TerminalSize> {
+    if cell_width <= 0.0 || cell_height <= 0.0 || width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+
+    let clamp = |cells: f64| (cells.floor() as u16).clamp(1, TerminalSize::MAX_DIMENSION);
+    Some(TerminalSize::new(
+        clamp(height / cell_height),
+        clamp(width / cell_width),
+    ))
 }
@@ -4,7 +4,7 @@ use ui::Navbar;
 use views::{Blog, Home, Terminal};
 
 // Import server functions to register them
-#[cfg(not(target_family = "wasm"))]
+#[cfg(feature = "server")]
 #[allow(unused_imports)]
 use api::{
     create_session, delete_session, get_allowed_directories, get_sessions, terminal_websocket,
@@ -48,7 +48,7 @@ fn main() {
 #[component]
 fn App() -> Element {
     // Initialize server-side state
-    #[cfg(not(target_family = "wasm"))]
+    #[cfg(feature = "server")]
     {
         use api::{AppState, Config};
         use std::sync::Once;
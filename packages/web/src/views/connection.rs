@@ -0,0 +1,134 @@
+use api::models::message::HeartbeatSettings;
+use std::fmt;
+use std::time::Duration;
+
+/// Delay before the first reconnection attempt
+const INITIAL_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between reconnection attempts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How often the client pings the server, and how long it waits to hear
+/// anything back before it gives up on the connection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Used until the server announces its own schedule
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(45),
+        }
+    }
+}
+
+/// The schedule the server announced in its `Welcome`
+impl From<HeartbeatSettings> for HeartbeatConfig {
+    fn from(settings: HeartbeatSettings) -> Self {
+        Self {
+            interval: Duration::from_secs(settings.interval_secs.max(1)),
+            timeout: Duration::from_secs(settings.timeout_secs),
+        }
+    }
+}
+
+/// Where the terminal WebSocket is, as shown in the status bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    /// Socket opened, waiting for the server's first message
+    Connecting { attempt: u32 },
+
+    /// The server is talking to us
+    Connected,
+
+    /// The connection dropped; the next attempt starts after `delay`
+    Reconnecting { attempt: u32, delay: Duration },
+}
+
+impl ConnectionState {
+    /// Extra class for the status bar
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting { .. } => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting { .. } => "reconnecting",
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connecting { attempt: 0 } => write!(f, "Connecting..."),
+            ConnectionState::Connecting { attempt } => {
+                write!(f, "Reconnecting (attempt {})...", attempt + 1)
+            }
+            ConnectionState::Connected => write!(f, "Connected"),
+            ConnectionState::Reconnecting { attempt, delay } => write!(
+                f,
+                "Disconnected, retrying in {:.1}s (attempt {})",
+                delay.as_secs_f64(),
+                attempt + 1
+            ),
+        }
+    }
+}
+
+/// Exponential backoff with jitter between reconnection attempts
+///
+/// Half of each delay is random, so browsers that lost the server at the
+/// same moment do not all come back at once.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    /// Failed attempts since the last successful connection
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Delay before the next attempt, growing with each failure
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = INITIAL_DELAY
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(MAX_DELAY);
+        self.attempt += 1;
+
+        let half = delay / 2;
+        half + half.mul_f64(jitter())
+    }
+
+    /// Start over after a connection the server answered
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Uniform random number in `[0, 1)`
+fn jitter() -> f64 {
+    #[cfg(target_family = "wasm")]
+    {
+        js_sys::Math::random()
+    }
+
+    // Effects only run in the browser; the clock is plenty for server builds
+    #[cfg(not(target_family = "wasm"))]
+    {
+        f64::from(chrono::Utc::now().timestamp_subsec_millis()) / 1000.0
+    }
+}
+
+/// Wait without blocking the browser's event loop
+pub async fn sleep(duration: Duration) {
+    #[cfg(target_family = "wasm")]
+    gloo_timers::future::TimeoutFuture::new(duration.as_millis() as u32).await;
+
+    #[cfg(not(target_family = "wasm"))]
+    tokio::time::sleep(duration).await;
+}
@@ -4,5 +4,7 @@ pub use home::Home;
 mod blog;
 pub use blog::Blog;
 
+mod connection;
+
 mod terminal;
 pub use terminal::Terminal;
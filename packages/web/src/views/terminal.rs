@@ -1,25 +1,40 @@
-use api::models::message::{ClientEvent, ServerEvent, SessionInfo};
-use api::{create_session, delete_session, get_allowed_directories, get_sessions};
+use super::connection::{sleep, Backoff, ConnectionState, HeartbeatConfig};
+use api::models::message::{
+    Capability, ClientEvent, FrameKind, OutputEncoding, OutputFrame, ServerEvent, SessionInfo,
+    PROTOCOL_VERSION,
+};
+use api::models::session::SessionStatus;
+use api::models::utf8::Utf8Decoder;
+use api::{
+    create_session, delete_session, get_allowed_directories, get_sessions, signal_session,
+    SessionSignal, TerminalSize,
+};
 use dioxus::prelude::*;
 use futures::channel::mpsc;
+use futures::future::poll_fn;
 use futures::{select, FutureExt};
 use futures_util::{SinkExt, StreamExt};
-use gloo_net::websocket::{futures::WebSocket, Message};
+use gloo_net::websocket::{futures::WebSocket, Message, State};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use ui::terminal::ansi::AnsiRenderer;
 use ui::terminal::{SessionList, SessionManager, TerminalView};
 
 /// Terminal view route
 #[component]
 pub fn Terminal() -> Element {
     // Application state
-    let sessions = use_signal(|| Vec::<SessionInfo>::new());
+    let sessions = use_signal(Vec::<SessionInfo>::new);
     let mut active_session_id = use_signal(|| Option::<String>::None);
-    let mut terminal_outputs = use_signal(|| HashMap::<String, Vec<String>>::new());
-    let connection_status = use_signal(|| "Connecting...".to_string());
-    let mut allowed_directories = use_signal(|| Vec::<String>::new());
+    // Each session's output, laid out with its colors as it arrives
+    let mut terminal_outputs = use_signal(HashMap::<String, Rc<AnsiRenderer>>::new);
+    // Stream offset reached by each session's output above, to resume from
+    let mut output_offsets = use_signal(HashMap::<String, u64>::new);
+    let connection_status = use_signal(|| ConnectionState::Connecting { attempt: 0 });
+    let mut allowed_directories = use_signal(Vec::<String>::new);
+    // Last character grid measured by the terminal view
+    let mut terminal_size = use_signal(|| Option::<TerminalSize>::None);
 
     // Fetch allowed directories on mount
     use_effect(move || {
@@ -36,22 +51,44 @@ pub fn Terminal() -> Element {
     });
 
     // Channel for sending messages to WebSocket
-    let tx = use_signal(|| None::<Arc<Mutex<mpsc::Sender<ClientEvent>>>>);
+    let tx = use_signal(|| None::<mpsc::UnboundedSender<ClientEvent>>);
 
-    // Establish WebSocket connection
+    let client_state = ClientState {
+        sessions,
+        active_session_id,
+        terminal_outputs,
+        output_offsets,
+        tx,
+    };
+
+    // Establish WebSocket connection, and re-establish it whenever it drops
     use_effect(move || {
-        let mut sessions_clone = sessions.clone();
-        let _active_session_id_clone = active_session_id.clone();
-        let mut terminal_outputs_clone = terminal_outputs.clone();
-        let mut connection_status_clone = connection_status.clone();
-        let mut tx_signal = tx.clone();
+        let mut sessions_clone = sessions;
+        let mut connection_status_clone = connection_status;
+        let mut tx_signal = tx;
 
         spawn(async move {
-            // Create channel for this WebSocket connection
-            let (tx_ws, mut rx) = mpsc::channel::<ClientEvent>(100);
+            // One queue for the component's lifetime, so events raised while
+            // disconnected go out once the connection is back. It is
+            // unbounded so event handlers can queue without awaiting: a
+            // bounded sender has to be shared behind a lock held across the
+            // send, which stalls the single threaded browser executor once
+            // the buffer fills. What it holds is limited to what the user
+            // does while the socket is down.
+            let (tx_ws, rx) = mpsc::unbounded::<ClientEvent>();
 
             // Store the sender so other parts can use it
-            tx_signal.set(Some(Arc::new(Mutex::new(tx_ws))));
+            tx_signal.set(Some(tx_ws));
+
+            let mut connection = Connection {
+                rx,
+                unsent: VecDeque::new(),
+                decoders: HashMap::new(),
+                backoff: Backoff::default(),
+                heartbeat: HeartbeatConfig::default(),
+                state: client_state,
+                status: connection_status_clone,
+            };
 
             // Load initial sessions from REST API
             match get_sessions().await {
@@ -66,7 +103,6 @@ pub fn Terminal() -> Element {
             // Build WebSocket URL dynamically based on current location
             #[cfg(target_family = "wasm")]
             let ws_url = {
-                use gloo_net::websocket::Message;
                 use web_sys::window as web_window;
 
                 let window = web_window().expect("no global window exists");
@@ -83,153 +119,101 @@ pub fn Terminal() -> Element {
             #[cfg(not(target_family = "wasm"))]
             let ws_url = "ws://localhost:8080/api/terminal".to_string();
 
-            tracing::info!("Connecting to WebSocket: {}", ws_url);
-
-            // Connect to WebSocket for terminal I/O
-            match WebSocket::open(&ws_url) {
-                Ok(mut socket) => {
-                    tracing::info!("WebSocket connected successfully");
-                    connection_status_clone.set("Connected".to_string());
-
-                    loop {
-                        select! {
-                            // Handle incoming WebSocket messages
-                            msg = socket.next().fuse() => {
-                                match msg {
-                                    Some(Ok(Message::Text(text))) => {
-                                        tracing::debug!("Received WebSocket message: {}", text);
-                                        match serde_json::from_str::<ServerEvent>(&text) {
-                                            Ok(event) => {
-                                                tracing::debug!("Parsed server event: {:?}", event);
-                                                handle_server_event(event, &mut terminal_outputs_clone);
-                                            }
-                                            Err(e) => {
-                                                tracing::error!("Failed to parse server event: {}", e);
-                                            }
-                                        }
-                                    }
-                                    Some(Ok(Message::Bytes(_))) => {}
-                                    Some(Err(e)) => {
-                                        tracing::error!("WebSocket error: {:?}", e);
-                                        connection_status_clone.set("Error".to_string());
-                                        break;
-                                    }
-                                    None => break,
-                                }
-                            }
-
-                            // Handle outgoing messages from channel
-                            event = rx.next().fuse() => {
-                                match event {
-                                    Some(event) => {
-                                        tracing::debug!("Sending client event via WebSocket: {:?}", event);
-                                        match serde_json::to_string(&event) {
-                                            Ok(json) => {
-                                                if let Err(e) = socket.send(Message::Text(json)).await {
-                                                    tracing::error!("Failed to send WebSocket message: {:?}", e);
-                                                    break;
-                                                }
-                                            }
-                                            Err(e) => {
-                                                tracing::error!("Failed to serialize event: {:?}", e);
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        tracing::warn!("Client event channel closed");
-                                        break;
-                                    }
-                                }
-                            }
+            loop {
+                connection_status_clone.set(ConnectionState::Connecting {
+                    attempt: connection.backoff.attempt(),
+                });
+                tracing::info!("Connecting to WebSocket: {}", ws_url);
+
+                // Connect to WebSocket for terminal I/O
+                match WebSocket::open(&ws_url) {
+                    Ok(socket) => {
+                        if connection.run(socket).await == ConnectionEnd::ChannelClosed {
+                            tracing::warn!("Client event channel closed");
+                            return;
                         }
                     }
+                    Err(e) => {
+                        tracing::error!("Failed to connect to WebSocket: {:?}", e);
+                    }
                 }
-                Err(e) => {
-                    tracing::error!("Failed to connect to WebSocket: {:?}", e);
-                    connection_status_clone.set("Failed to connect".to_string());
-                }
+
+                let delay = connection.backoff.next_delay();
+                tracing::info!("Reconnecting to WebSocket in {:?}", delay);
+                connection_status_clone.set(ConnectionState::Reconnecting {
+                    attempt: connection.backoff.attempt(),
+                    delay,
+                });
+                sleep(delay).await;
             }
         });
     });
 
     // Event handlers
-    let handle_create_session = Rc::new(RefCell::new(move |name: String, directory: String, command: String| {
-        let mut sessions = sessions.clone();
-        let mut active_session_id = active_session_id.clone();
-        let tx_clone = tx.clone();
-        spawn(async move {
-            match create_session(name.clone(), directory.clone()).await {
-                Ok(session_id) => {
-                    tracing::info!("Session created: {}", session_id);
-
-                    // Add to local session list
-                    let mut current_sessions = sessions.read().clone();
-                    current_sessions.push(SessionInfo {
-                        id: session_id.clone(),
-                        name,
-                        directory,
-                        created_at: chrono::Utc::now().to_rfc3339(),
-                    });
-                    sessions.set(current_sessions);
-
-                    // Automatically select the newly created session
-                    active_session_id.set(Some(session_id.clone()));
+    let handle_create_session = Rc::new(RefCell::new(
+        move |name: String, directory: String, command: String| {
+            let mut sessions = sessions;
+            spawn(async move {
+                let size = *terminal_size.peek();
+                // The server types the task in itself once the session is ready
+                let initial_prompt = (!command.is_empty()).then_some(command);
+                match create_session(name.clone(), directory.clone(), size, initial_prompt).await {
+                    Ok(session_id) => {
+                        tracing::info!("Session created: {}", session_id);
+
+                        // Add to local session list, unless the server already announced it
+                        let known = sessions.read().iter().any(|s| s.id == session_id);
+                        if !known {
+                            sessions.write().push(SessionInfo {
+                                id: session_id.clone(),
+                                name,
+                                directory,
+                                created_at: chrono::Utc::now().to_rfc3339(),
+                                status: SessionStatus::Starting,
+                            });
+                        }
 
-                    // Wait for session to be fully initialized on the server
-                    // TODO: Replace with a proper "session ready" event from server
-                    #[cfg(target_family = "wasm")]
-                    {
-                        use gloo_timers::future::TimeoutFuture;
-                        TimeoutFuture::new(500).await;
+                        // Automatically select the newly created session and attach to its output
+                        activate_session(active_session_id, output_offsets, tx, session_id);
                     }
-
-                    #[cfg(not(target_family = "wasm"))]
-                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-                    // Send the command to the session after it's ready
-                    if !command.is_empty() {
-                        tracing::info!("Sending command to session {}: {}", session_id, command);
-                        if let Some(tx_arc) = tx_clone.read().as_ref() {
-                            if let Ok(mut tx_guard) = tx_arc.lock() {
-                                let _ = tx_guard.send(ClientEvent::SendInput {
-                                    session_id,
-                                    input: format!("{}\n", command)
-                                }).await;
-                            }
-                        } else {
-                            tracing::warn!("WebSocket sender not ready");
-                        }
+                    Err(e) => {
+                        tracing::error!("Failed to create session: {:?}", e);
                     }
                 }
-                Err(e) => {
-                    tracing::error!("Failed to create session: {:?}", e);
-                }
-            }
-        });
-    }));
+            });
+        },
+    ));
 
     let handle_select_session = Rc::new(RefCell::new(move |session_id: String| {
-        active_session_id.set(Some(session_id.clone()));
+        // The session may have been sized for another view; match ours
+        if let Some(size) = *terminal_size.peek() {
+            send_client_event(
+                tx,
+                ClientEvent::Resize {
+                    session_id: session_id.clone(),
+                    size,
+                },
+            );
+        }
 
-        // Request buffered history for this session via WebSocket
-        let tx_clone = tx.clone();
-        spawn(async move {
-            if let Some(tx_arc) = tx_clone.read().as_ref() {
-                if let Ok(mut tx_guard) = tx_arc.lock() {
-                    let _ = tx_guard.send(ClientEvent::RequestHistory { session_id }).await;
-                }
-            } else {
-                tracing::warn!("WebSocket sender not ready");
-            }
-        });
+        // Stream its history and output instead of the previous session's
+        activate_session(active_session_id, output_offsets, tx, session_id);
     }));
 
     let handle_close_session = Rc::new(RefCell::new(move |session_id: String| {
-        let mut sessions = sessions.clone();
+        let mut sessions = sessions;
         let session_id_clone = session_id.clone();
         spawn(async move {
             match delete_session(session_id_clone.clone()).await {
-                Ok(_) => {
+                Ok(shutdown) => {
+                    if !shutdown.clean {
+                        tracing::warn!(
+                            "Session {} had to be killed: {:?}",
+                            session_id_clone,
+                            shutdown.exit_status
+                        );
+                    }
+
                     // Remove from local session list
                     let mut current_sessions = sessions.read().clone();
                     current_sessions.retain(|s| s.id != session_id_clone);
@@ -237,6 +221,7 @@ pub fn Terminal() -> Element {
 
                     // Clear from local state
                     terminal_outputs.write().remove(&session_id_clone);
+                    output_offsets.write().remove(&session_id_clone);
                     if active_session_id.read().as_ref() == Some(&session_id_clone) {
                         active_session_id.set(None);
                     }
@@ -250,20 +235,38 @@ pub fn Terminal() -> Element {
 
     let handle_send_input = Rc::new(RefCell::new(move |input: String| {
         if let Some(ref session_id) = *active_session_id.read() {
-            let tx_clone = tx.clone();
             let session_id = session_id.clone();
-            spawn(async move {
-                if let Some(tx_arc) = tx_clone.read().as_ref() {
-                    if let Ok(mut tx_guard) = tx_arc.lock() {
-                        let _ = tx_guard.send(ClientEvent::SendInput { session_id, input }).await;
-                    }
-                } else {
-                    tracing::warn!("WebSocket sender not ready");
-                }
-            });
+            send_client_event(tx, ClientEvent::SendInput { session_id, input });
         }
     }));
 
+    // The new status reaches the list through the session change it causes
+    let handle_signal_session = move |(session_id, signal): (String, SessionSignal)| {
+        spawn(async move {
+            if let Err(e) = signal_session(session_id.clone(), signal).await {
+                tracing::error!(
+                    "Failed to send {:?} to session {}: {:?}",
+                    signal,
+                    session_id,
+                    e
+                );
+            }
+        });
+    };
+
+    let handle_send_keys = move |keys: String| {
+        if let Some(session_id) = active_session_id.peek().clone() {
+            send_client_event(tx, ClientEvent::SendKeys { session_id, keys });
+        }
+    };
+
+    let handle_resize = move |size: TerminalSize| {
+        terminal_size.set(Some(size));
+        if let Some(session_id) = active_session_id.peek().clone() {
+            send_client_event(tx, ClientEvent::Resize { session_id, size });
+        }
+    };
+
     rsx! {
         div { class: "terminal-container",
 
@@ -282,6 +285,7 @@ pub fn Terminal() -> Element {
                     active_session_id: active_session_id.read().clone(),
                     on_select: move |id| handle_select_session.borrow_mut()(id),
                     on_close: move |id| handle_close_session.borrow_mut()(id),
+                    on_signal: handle_signal_session,
                 }
             }
 
@@ -293,6 +297,8 @@ pub fn Terminal() -> Element {
                         session_id: session_id.clone(),
                         output: terminal_outputs.read().get(session_id).cloned().unwrap_or_default(),
                         on_send_input: move |input| handle_send_input.borrow_mut()(input),
+                        on_send_keys: handle_send_keys,
+                        on_resize: handle_resize,
                     }
                 } else {
                     div { class: "no-session-selected",
@@ -303,24 +309,389 @@ pub fn Terminal() -> Element {
             }
 
             // Status bar
-            div { class: "status-bar", "Status: {connection_status}" }
+            div { class: "status-bar {connection_status.read().css_class()}", "Status: {connection_status}" }
         }
     }
 }
 
-/// Handle incoming server events and update state
-fn handle_server_event(
-    event: ServerEvent,
-    terminal_outputs: &mut Signal<HashMap<String, Vec<String>>>,
+/// Why a connection ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionEnd {
+    /// The socket failed or the server went away; worth reconnecting
+    Lost,
+    /// Nothing can send events any more
+    ChannelClosed,
+}
+
+/// State the WebSocket task keeps from one connection to the next
+struct Connection {
+    /// Events raised by the view, queued while disconnected
+    rx: mpsc::UnboundedReceiver<ClientEvent>,
+    /// Input that may not have reached the server before the socket dropped
+    unsent: VecDeque<ClientEvent>,
+    /// Streaming decoders for binary output, one per session, matching the
+    /// output offsets
+    decoders: HashMap<String, Utf8Decoder>,
+    backoff: Backoff,
+    heartbeat: HeartbeatConfig,
+    state: ClientState,
+    status: Signal<ConnectionState>,
+}
+
+impl Connection {
+    /// Drive one WebSocket connection until it drops
+    ///
+    /// The server forgets a socket's encoding and subscriptions when it closes,
+    /// so they are set up again first, resuming the active session after the
+    /// output already shown. Binary output is asked for once the server's
+    /// `Welcome` says it can send it. Input the previous connection failed to send
+    /// follows, then whatever was queued meanwhile. A server that stays silent
+    /// through the heartbeat timeout it announced counts as gone, even if the
+    /// socket never reports an error.
+    async fn run(&mut self, mut socket: WebSocket) -> ConnectionEnd {
+        let state = self.state;
+        // The server starts every stream afresh on a new socket, so
+        // characters split before the reconnect will never be completed
+        self.decoders.clear();
+        let mut restore = vec![ClientEvent::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: vec![
+                Capability::BinaryOutput,
+                Capability::Resume,
+                Capability::Heartbeat,
+                Capability::Resync,
+                Capability::RawKeys,
+                Capability::Signals,
+            ],
+        }];
+        if let Some(session_id) = state.active_session_id.peek().clone() {
+            let after = state.output_offsets.peek().get(&session_id).copied();
+            restore.push(ClientEvent::Subscribe { session_id, after });
+        }
+        for event in restore {
+            if send_event(&mut socket, event).await.is_err() {
+                return ConnectionEnd::Lost;
+            }
+        }
+        while let Some(event) = self.unsent.pop_front() {
+            if let Err(event) = send_event(&mut socket, event).await {
+                self.unsent.push_front(event);
+                return ConnectionEnd::Lost;
+            }
+        }
+
+        let mut last_heard = chrono::Utc::now();
+        let mut ping_timer = sleep(self.heartbeat.interval).boxed_local().fuse();
+        let mut ping_nonce = 0;
+
+        loop {
+            select! {
+                // Handle incoming WebSocket messages
+                msg = socket.next().fuse() => {
+                    let payload = match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            tracing::debug!("Received WebSocket message: {}", text);
+                            text.into_bytes()
+                        }
+                        // Raw output frames, or events as binary JSON from typed server sockets
+                        Some(Ok(Message::Bytes(bytes))) => bytes,
+                        Some(Err(e)) => {
+                            tracing::error!("WebSocket error: {:?}", e);
+                            return ConnectionEnd::Lost;
+                        }
+                        None => {
+                            tracing::warn!("WebSocket closed by the server");
+                            return ConnectionEnd::Lost;
+                        }
+                    };
+
+                    last_heard = chrono::Utc::now();
+
+                    // The server announces itself right away, so the first
+                    // message marks a working connection
+                    if *self.status.peek() != ConnectionState::Connected {
+                        tracing::info!("WebSocket connected successfully");
+                        self.status.set(ConnectionState::Connected);
+                        self.backoff.reset();
+                    }
+                    if let Some(ServerEvent::Welcome {
+                        protocol_version,
+                        server_version,
+                        capabilities,
+                        heartbeat,
+                    }) = handle_server_payload(&payload, &mut self.decoders, state)
+                    {
+                        tracing::info!(
+                            "Server {} speaks protocol version {} with {:?}",
+                            server_version,
+                            protocol_version,
+                            capabilities
+                        );
+                        if protocol_version != PROTOCOL_VERSION {
+                            tracing::warn!(
+                                "Server only speaks protocol version {}, expected {}",
+                                protocol_version,
+                                PROTOCOL_VERSION
+                            );
+                        }
+                        // Ping as often as the server expects to hear from us
+                        self.heartbeat = heartbeat.into();
+                        ping_timer = sleep(self.heartbeat.interval).boxed_local().fuse();
+
+                        if capabilities.contains(&Capability::BinaryOutput) {
+                            let event = ClientEvent::SetOutputEncoding {
+                                encoding: OutputEncoding::Binary,
+                            };
+                            if send_event(&mut socket, event).await.is_err() {
+                                return ConnectionEnd::Lost;
+                            }
+                        }
+                    }
+                }
+
+                // Handle outgoing messages from channel
+                event = self.rx.next().fuse() => {
+                    let Some(event) = event else {
+                        return ConnectionEnd::ChannelClosed;
+                    };
+                    tracing::debug!("Sending client event via WebSocket: {:?}", event);
+                    if let Err(event) = send_event(&mut socket, event).await {
+                        // Subscriptions and the encoding are rebuilt from state
+                        // on reconnect, input and resizes have to be repeated
+                        if matches!(
+                            event,
+                            ClientEvent::SendInput { .. }
+                                | ClientEvent::SendKeys { .. }
+                                | ClientEvent::Resize { .. }
+                        ) {
+                            self.unsent.push_back(event);
+                        }
+                        return ConnectionEnd::Lost;
+                    }
+                }
+
+                _ = ping_timer => {
+                    let silent = (chrono::Utc::now() - last_heard).to_std().unwrap_or_default();
+                    if silent > self.heartbeat.timeout {
+                        tracing::warn!("No heartbeat from the server for {:?}", silent);
+                        return ConnectionEnd::Lost;
+                    }
+                    ping_nonce += 1;
+                    if send_event(&mut socket, ClientEvent::Ping { nonce: ping_nonce }).await.is_err() {
+                        return ConnectionEnd::Lost;
+                    }
+                    ping_timer = sleep(self.heartbeat.interval).boxed_local().fuse();
+                }
+            }
+        }
+    }
+}
+
+/// Send an event over the socket, handing it back if the socket is not open
+///
+/// Browsers silently drop messages sent on a closing socket, so the state
+/// is checked once the opening handshake is over.
+async fn send_event(socket: &mut WebSocket, event: ClientEvent) -> Result<(), ClientEvent> {
+    if poll_fn(|cx| socket.poll_ready_unpin(cx)).await.is_err()
+        || !matches!(socket.state(), State::Open)
+    {
+        return Err(event);
+    }
+
+    let json = match serde_json::to_string(&event) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::error!("Failed to serialize event: {:?}", e);
+            return Ok(());
+        }
+    };
+    match socket.send(Message::Text(json)).await {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            tracing::error!("Failed to send WebSocket message: {:?}", e);
+            Err(event)
+        }
+    }
+}
+
+/// Signals updated from server events
+#[derive(Clone, Copy)]
+struct ClientState {
+    sessions: Signal<Vec<SessionInfo>>,
+    active_session_id: Signal<Option<String>>,
+    terminal_outputs: Signal<HashMap<String, Rc<AnsiRenderer>>>,
+    output_offsets: Signal<HashMap<String, u64>>,
+    tx: Signal<Option<mpsc::UnboundedSender<ClientEvent>>>,
+}
+
+/// Queue a client event for the WebSocket connection task
+fn send_client_event(tx: Signal<Option<mpsc::UnboundedSender<ClientEvent>>>, event: ClientEvent) {
+    match tx.read().as_ref() {
+        Some(sender) => {
+            if let Err(e) = sender.unbounded_send(event) {
+                tracing::warn!("WebSocket sender closed: {:?}", e);
+            }
+        }
+        None => tracing::warn!("WebSocket sender not ready"),
+    }
+}
+
+/// Make a session the active one, moving the output subscription over to it
+///
+/// Subscribing resumes after the output already shown, so only what was
+/// missed while the session was in the background is sent.
+fn activate_session(
+    mut active_session_id: Signal<Option<String>>,
+    output_offsets: Signal<HashMap<String, u64>>,
+    tx: Signal<Option<mpsc::UnboundedSender<ClientEvent>>>,
+    session_id: String,
 ) {
+    let previous = active_session_id.peek().clone();
+    if let Some(previous) = previous.filter(|previous| *previous != session_id) {
+        send_client_event(
+            tx,
+            ClientEvent::Unsubscribe {
+                session_id: previous,
+            },
+        );
+    }
+
+    active_session_id.set(Some(session_id.clone()));
+    let after = output_offsets.peek().get(&session_id).copied();
+    send_client_event(tx, ClientEvent::Subscribe { session_id, after });
+}
+
+/// Decode a server event or raw output frame from a WebSocket message
+///
+/// The server's `Welcome` is handed back rather than handled, since it
+/// concerns the connection instead of the view.
+fn handle_server_payload(
+    payload: &[u8],
+    decoders: &mut HashMap<String, Utf8Decoder>,
+    state: ClientState,
+) -> Option<ServerEvent> {
+    if let Some(frame) = OutputFrame::decode(payload) {
+        let decoder = decoders.entry(frame.session_id.clone()).or_default();
+        let event = match frame.kind {
+            FrameKind::Output => {
+                // Resubscribing after a reconnect can repeat output already
+                // shown; only the part past our offset is new
+                let start = frame.offset.saturating_sub(frame.data.len() as u64);
+                let seen = state
+                    .output_offsets
+                    .peek()
+                    .get(&frame.session_id)
+                    .map_or(0, |&offset| offset.saturating_sub(start));
+                if seen >= frame.data.len() as u64 {
+                    return None;
+                }
+                ServerEvent::TerminalOutput {
+                    data: decoder.decode(&frame.data[seen as usize..]),
+                    session_id: frame.session_id,
+                    offset: frame.offset,
+                }
+            }
+            FrameKind::History => {
+                // A snapshot restarts the stream for this session
+                *decoder = Utf8Decoder::new();
+                ServerEvent::SessionHistory {
+                    data: decoder.decode(&frame.data),
+                    session_id: frame.session_id,
+                    offset: frame.offset,
+                }
+            }
+        };
+        handle_server_event(event, state);
+        return None;
+    }
+
+    match serde_json::from_slice::<ServerEvent>(payload) {
+        Ok(event) => {
+            tracing::debug!("Parsed server event: {:?}", event);
+            if let ServerEvent::SessionClosed { session_id, .. }
+            | ServerEvent::SessionRemoved { session_id } = &event
+            {
+                decoders.remove(session_id);
+            }
+            if matches!(event, ServerEvent::Welcome { .. }) {
+                return Some(event);
+            }
+            handle_server_event(event, state);
+        }
+        Err(e) => {
+            tracing::error!("Failed to parse server event: {}", e);
+        }
+    }
+    None
+}
+
+/// Handle incoming server events and update state
+fn handle_server_event(event: ServerEvent, state: ClientState) {
+    let ClientState {
+        mut sessions,
+        mut active_session_id,
+        mut terminal_outputs,
+        mut output_offsets,
+        tx,
+    } = state;
+
     match event {
-        ServerEvent::TerminalOutput { session_id, data } => {
+        ServerEvent::TerminalOutput {
+            session_id,
+            data,
+            offset,
+        } => {
+            output_offsets.write().insert(session_id.clone(), offset);
+            if data.is_empty() {
+                // Only part of a character arrived so far
+                return;
+            }
             tracing::debug!("Terminal output for session {}: {}", session_id, data);
-            terminal_outputs
-                .write()
-                .entry(session_id)
-                .or_insert_with(Vec::new)
-                .push(data);
+            Rc::make_mut(terminal_outputs.write().entry(session_id).or_default()).feed(&data);
+        }
+
+        ServerEvent::SessionHistory {
+            session_id,
+            data,
+            offset,
+        } => {
+            output_offsets.write().insert(session_id.clone(), offset);
+            tracing::debug!("History for session {}: {} bytes", session_id, data.len());
+            let mut output = AnsiRenderer::default();
+            output.feed(&data);
+            terminal_outputs.write().insert(session_id, Rc::new(output));
+        }
+
+        ServerEvent::ResyncNeeded { session_id } => {
+            tracing::warn!("Fell behind on session {}, fetching history", session_id);
+            send_client_event(tx, ClientEvent::RequestHistory { session_id });
+        }
+
+        ServerEvent::SessionReady { session_id } => {
+            tracing::info!("Session {} is ready", session_id);
+        }
+
+        ServerEvent::Ping { nonce } => {
+            send_client_event(tx, ClientEvent::Pong { nonce });
+        }
+
+        // Any message from the server counts as a sign of life
+        ServerEvent::Pong { .. } => {}
+
+        ServerEvent::OutputEncodingChanged { encoding } => {
+            tracing::debug!("Server switched output encoding to {:?}", encoding);
+        }
+
+        // Taken care of by the connection, see `handle_server_payload`
+        ServerEvent::Welcome { .. } => {}
+
+        ServerEvent::ProtocolError(error) => {
+            tracing::error!(
+                "Server rejected {:?}: {:?}: {}",
+                error.event_type,
+                error.kind,
+                error.message
+            );
         }
 
         ServerEvent::Error { message } => {
@@ -328,13 +699,62 @@ fn handle_server_event(
             // TODO: Show error to user in UI
         }
 
-        // Session management events are not expected on this WebSocket
-        ServerEvent::SessionList { .. }
-        | ServerEvent::SessionCreated { .. }
-        | ServerEvent::SessionClosed { .. } => {
-            tracing::warn!(
-                "Received unexpected session management event - these should use REST API"
+        ServerEvent::SessionClosed { session_id, reason } => {
+            tracing::info!("Session {} closed: {:?}", session_id, reason);
+            let notice = format!(
+                "\n[{}]\n",
+                reason.unwrap_or_else(|| "Session closed".to_string())
             );
+            Rc::make_mut(terminal_outputs.write().entry(session_id).or_default()).feed(&notice);
+        }
+
+        ServerEvent::SessionList {
+            sessions: session_list,
+        } => {
+            tracing::debug!("Session list with {} sessions", session_list.len());
+            let listed = |id: &String| session_list.iter().any(|s| &s.id == id);
+            terminal_outputs.write().retain(|id, _| listed(id));
+            output_offsets.write().retain(|id, _| listed(id));
+            let active_gone = active_session_id
+                .peek()
+                .as_ref()
+                .is_some_and(|id| !session_list.iter().any(|s| &s.id == id));
+            if active_gone {
+                active_session_id.set(None);
+            }
+            sessions.set(session_list);
+        }
+
+        // Sessions are created over the REST API, list changes follow as
+        // `SessionAdded`
+        ServerEvent::SessionCreated { session_id, .. } => {
+            tracing::debug!("Session {} created", session_id);
+        }
+
+        ServerEvent::SessionAdded { session } => {
+            tracing::info!("Session {} added", session.id);
+            let mut sessions = sessions.write();
+            match sessions.iter_mut().find(|s| s.id == session.id) {
+                Some(existing) => *existing = session,
+                None => sessions.push(session),
+            }
+        }
+
+        ServerEvent::SessionStatusChanged { session_id, status } => {
+            tracing::debug!("Session {} is now {:?}", session_id, status);
+            if let Some(session) = sessions.write().iter_mut().find(|s| s.id == session_id) {
+                session.status = status;
+            }
+        }
+
+        ServerEvent::SessionRemoved { session_id } => {
+            tracing::info!("Session {} removed", session_id);
+            sessions.write().retain(|s| s.id != session_id);
+            terminal_outputs.write().remove(&session_id);
+            output_offsets.write().remove(&session_id);
+            if active_session_id.peek().as_ref() == Some(&session_id) {
+                active_session_id.set(None);
+            }
         }
     }
 }